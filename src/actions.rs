@@ -0,0 +1,522 @@
+//! Named input actions with rebindable bindings
+//!
+//! Games query [`ActionState`] for [`Action`]s (e.g. whether `sprint` is
+//! pressed) instead of reading keys or gamepad buttons directly. Each action
+//! has some default [`Binding`]s, which users can change (e.g. with the
+//! [`Controller`](crate::control::Controller)'s `rebind` method).
+//!
+//! Custom bindings are saved in `localStorage` on the web, and in a config file
+//! natively (`web-bg/bindings.txt` in the user's config directory). They are
+//! stored as text, with one action per line, for example:
+//!
+//! ```text
+//! move-up = key:KeyW, key:ArrowUp, button:DPadUp, axis:LeftStickY+
+//! sprint = key:ShiftLeft, mouse:Right
+//! ```
+
+use std::{
+	collections::HashMap,
+	fmt::{Display, Formatter, Result as FmtResult},
+	str::FromStr,
+};
+
+use bevy::{
+	prelude::*,
+	reflect::{DynamicEnum, DynamicVariant},
+};
+
+use crate::control::{Pause, PauseReason};
+
+/// The key under which custom bindings are saved in `localStorage`
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "web-bg-bindings";
+
+/// How far an action has to be pressed (e.g. with an analog stick) to count as
+/// pressed
+pub const PRESS_THRESHOLD: f32 = 0.5;
+
+/// An input action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Action {
+	MoveUp,
+	MoveDown,
+	MoveLeft,
+	MoveRight,
+	/// Interact with something in the game
+	Interact,
+	/// Move faster
+	Sprint,
+	/// Pause or resume the game
+	Pause,
+	ZoomIn,
+	ZoomOut,
+}
+
+impl Action {
+	/// All actions
+	pub const ALL: [Self; 9] = [
+		Self::MoveUp,
+		Self::MoveDown,
+		Self::MoveLeft,
+		Self::MoveRight,
+		Self::Interact,
+		Self::Sprint,
+		Self::Pause,
+		Self::ZoomIn,
+		Self::ZoomOut,
+	];
+
+	/// Get the name of this action (e.g. `"move-up"`)
+	#[must_use]
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::MoveUp => "move-up",
+			Self::MoveDown => "move-down",
+			Self::MoveLeft => "move-left",
+			Self::MoveRight => "move-right",
+			Self::Interact => "interact",
+			Self::Sprint => "sprint",
+			Self::Pause => "pause",
+			Self::ZoomIn => "zoom-in",
+			Self::ZoomOut => "zoom-out",
+		}
+	}
+
+	/// Get the default bindings of this action
+	#[must_use]
+	pub fn default_bindings(self) -> Vec<Binding> {
+		use Binding::{Axis, Button, Key};
+
+		match self {
+			Self::MoveUp => vec![
+				Key(KeyCode::KeyW),
+				Key(KeyCode::ArrowUp),
+				Button(GamepadButton::DPadUp),
+				Axis(GamepadAxis::LeftStickY, true),
+			],
+			Self::MoveDown => vec![
+				Key(KeyCode::KeyS),
+				Key(KeyCode::ArrowDown),
+				Button(GamepadButton::DPadDown),
+				Axis(GamepadAxis::LeftStickY, false),
+			],
+			Self::MoveLeft => vec![
+				Key(KeyCode::KeyA),
+				Key(KeyCode::ArrowLeft),
+				Button(GamepadButton::DPadLeft),
+				Axis(GamepadAxis::LeftStickX, false),
+			],
+			Self::MoveRight => vec![
+				Key(KeyCode::KeyD),
+				Key(KeyCode::ArrowRight),
+				Button(GamepadButton::DPadRight),
+				Axis(GamepadAxis::LeftStickX, true),
+			],
+			Self::Interact => vec![
+				Key(KeyCode::KeyE),
+				Key(KeyCode::Space),
+				Button(GamepadButton::South),
+			],
+			Self::Sprint => vec![
+				Key(KeyCode::ShiftLeft),
+				Key(KeyCode::ShiftRight),
+				Button(GamepadButton::LeftThumb),
+			],
+			Self::Pause => vec![Key(KeyCode::KeyP), Button(GamepadButton::Start)],
+			Self::ZoomIn => vec![
+				Key(KeyCode::Equal),
+				Key(KeyCode::NumpadAdd),
+				Button(GamepadButton::RightTrigger2),
+			],
+			Self::ZoomOut => vec![
+				Key(KeyCode::Minus),
+				Key(KeyCode::NumpadSubtract),
+				Button(GamepadButton::LeftTrigger2),
+			],
+		}
+	}
+}
+
+impl FromStr for Action {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::ALL
+			.into_iter()
+			.find(|action| action.name() == s)
+			.ok_or_else(|| format!("unknown action '{s}'"))
+	}
+}
+
+impl Display for Action {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		f.write_str(self.name())
+	}
+}
+
+/// An input which can trigger an [`Action`]
+///
+/// Bindings are written as the kind of input and its name, separated by a
+/// colon, with axis bindings ending in the axis' direction (e.g. `key:KeyW`,
+/// `mouse:Left`, `button:South`, or `axis:LeftStickX+`). Input names are the
+/// same as the names of Bevy's [`KeyCode`], [`MouseButton`], [`GamepadButton`],
+/// and [`GamepadAxis`] variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+	Key(KeyCode),
+	Mouse(MouseButton),
+	Button(GamepadButton),
+	/// A gamepad axis, in the positive (`true`) or negative (`false`) direction
+	Axis(GamepadAxis, bool),
+}
+
+impl Binding {
+	/// Get how far this binding is pressed, from `0.0` to `1.0`
+	fn value(
+		self,
+		keys: &ButtonInput<KeyCode>,
+		mouse: &ButtonInput<MouseButton>,
+		gamepads: &Query<&Gamepad>,
+	) -> f32 {
+		match self {
+			Self::Key(key) => pressed(keys.pressed(key)),
+			Self::Mouse(button) => pressed(mouse.pressed(button)),
+			Self::Button(_) | Self::Axis(..) => gamepads
+				.iter()
+				.map(|gamepad| self.gamepad_value(gamepad))
+				.fold(0.0, f32::max),
+		}
+	}
+
+	/// Get how far this binding is pressed on `gamepad`, from `0.0` to `1.0`
+	///
+	/// Analog buttons (e.g. the triggers) can be pressed partially. Bindings
+	/// which are not gamepad buttons or axes are never pressed on a gamepad
+	fn gamepad_value(self, gamepad: &Gamepad) -> f32 {
+		match self {
+			Self::Key(_) | Self::Mouse(_) => 0.0,
+			Self::Button(button) => gamepad
+				.get(button)
+				.unwrap_or_default()
+				.clamp(0.0, 1.0)
+				.max(pressed(gamepad.pressed(button))),
+			Self::Axis(axis, positive) => {
+				let value = gamepad.get(axis).unwrap_or_default();
+				let value = if positive { value } else { -value };
+				value.clamp(0.0, 1.0)
+			}
+		}
+	}
+}
+
+/// Get the value of a button which is pressed or not
+const fn pressed(is_pressed: bool) -> f32 {
+	if is_pressed { 1.0 } else { 0.0 }
+}
+
+impl FromStr for Binding {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("invalid binding '{s}'");
+
+		let (kind, name) = s.trim().split_once(':').ok_or_else(invalid)?;
+
+		match kind {
+			"key" => variant(name).map(Self::Key),
+			"mouse" => variant(name).map(Self::Mouse),
+			"button" => variant(name).map(Self::Button),
+			"axis" => {
+				let (name, positive) = match name.split_at_checked(name.len().saturating_sub(1)) {
+					Some((name, "+")) => (name, true),
+					Some((name, "-")) => (name, false),
+					_ => return Err(invalid()),
+				};
+
+				variant(name).map(|axis| Self::Axis(axis, positive))
+			}
+			_ => None,
+		}
+		.ok_or_else(invalid)
+	}
+}
+
+impl Display for Binding {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		match self {
+			Self::Key(key) => write!(f, "key:{key:?}"),
+			Self::Mouse(button) => write!(f, "mouse:{button:?}"),
+			Self::Button(button) => write!(f, "button:{button:?}"),
+			Self::Axis(axis, true) => write!(f, "axis:{axis:?}+"),
+			Self::Axis(axis, false) => write!(f, "axis:{axis:?}-"),
+		}
+	}
+}
+
+/// Get the unit variant of `T` called `name` (e.g. `KeyCode::KeyW` from
+/// `"KeyW"`), using reflection
+fn variant<T: FromReflect>(name: &str) -> Option<T> {
+	T::from_reflect(&DynamicEnum::new(name, DynamicVariant::Unit))
+}
+
+/// Parse a list of comma-separated bindings
+///
+/// # Errors
+/// This function returns an error if any of the bindings is invalid
+pub fn parse_bindings(s: &str) -> Result<Vec<Binding>, String> {
+	s.split(',')
+		.filter(|b| !b.trim().is_empty())
+		.map(str::parse)
+		.collect()
+}
+
+/// The bindings of all actions
+#[derive(Debug, Clone, PartialEq, Eq, Resource)]
+pub struct Bindings(HashMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+	fn default() -> Self {
+		Self(
+			Action::ALL
+				.into_iter()
+				.map(|action| (action, action.default_bindings()))
+				.collect(),
+		)
+	}
+}
+
+impl Bindings {
+	/// Load the saved bindings, using the defaults for actions without saved
+	/// bindings
+	#[must_use]
+	pub fn load() -> Self {
+		let mut bindings = Self::default();
+
+		let Some(saved) = load_saved() else {
+			return bindings;
+		};
+
+		for line in saved.lines() {
+			let line = line.trim();
+
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let parsed = line
+				.split_once('=')
+				.ok_or_else(|| format!("invalid line '{line}'"))
+				.and_then(|(action, list)| Ok((action.trim().parse()?, parse_bindings(list)?)));
+
+			match parsed {
+				Ok((action, list)) => bindings.set(action, list),
+				Err(e) => warn!("ignoring saved binding: {e}"),
+			}
+		}
+
+		bindings
+	}
+
+	/// Save the bindings, so that they are used the next time
+	pub fn save(&self) {
+		store_saved(&self.to_string());
+	}
+
+	/// Get the bindings of `action`
+	#[must_use]
+	pub fn get(&self, action: Action) -> &[Binding] {
+		self.0.get(&action).map_or(&[], Vec::as_slice)
+	}
+
+	/// Get how far `action` is pressed with its `n`th key binding (counting
+	/// only key bindings), from `0.0` to `1.0`
+	///
+	/// This is used to split the keyboard between multiple players (e.g. WASD
+	/// and arrow keys)
+	#[must_use]
+	pub fn key_value(&self, action: Action, n: usize, keys: &ButtonInput<KeyCode>) -> f32 {
+		self.get(action)
+			.iter()
+			.filter_map(|binding| match binding {
+				Binding::Key(key) => Some(*key),
+				_ => None,
+			})
+			.nth(n)
+			.map_or(0.0, |key| pressed(keys.pressed(key)))
+	}
+
+	/// Get how far `action` is pressed with its mouse bindings, from `0.0` to
+	/// `1.0`
+	#[must_use]
+	pub fn mouse_value(&self, action: Action, mouse: &ButtonInput<MouseButton>) -> f32 {
+		self.get(action)
+			.iter()
+			.filter_map(|binding| match binding {
+				Binding::Mouse(button) => Some(pressed(mouse.pressed(*button))),
+				_ => None,
+			})
+			.fold(0.0, f32::max)
+	}
+
+	/// Get how far `action` is pressed on `gamepad`, from `0.0` to `1.0`
+	#[must_use]
+	pub fn gamepad_value(&self, action: Action, gamepad: &Gamepad) -> f32 {
+		self.get(action)
+			.iter()
+			.map(|binding| binding.gamepad_value(gamepad))
+			.fold(0.0, f32::max)
+	}
+
+	/// Change the bindings of `action`
+	pub fn set(&mut self, action: Action, bindings: Vec<Binding>) {
+		self.0.insert(action, bindings);
+	}
+}
+
+impl Display for Bindings {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		for action in Action::ALL {
+			write!(f, "{action} =")?;
+
+			for (i, binding) in self.get(action).iter().enumerate() {
+				let separator = if i == 0 { " " } else { ", " };
+				write!(f, "{separator}{binding}")?;
+			}
+
+			writeln!(f)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Load the saved bindings text from `localStorage`
+#[cfg(target_arch = "wasm32")]
+fn load_saved() -> Option<String> {
+	web_sys::window()?
+		.local_storage()
+		.ok()??
+		.get_item(STORAGE_KEY)
+		.ok()?
+}
+
+/// Save the bindings text in `localStorage`
+#[cfg(target_arch = "wasm32")]
+fn store_saved(text: &str) {
+	let storage = web_sys::window().and_then(|w| w.local_storage().ok().flatten());
+
+	if storage.is_none_or(|s| s.set_item(STORAGE_KEY, text).is_err()) {
+		warn!("could not save bindings to `localStorage`");
+	}
+}
+
+/// Get the path of the bindings config file
+#[cfg(not(target_arch = "wasm32"))]
+fn config_path() -> Option<std::path::PathBuf> {
+	use std::{env, path::PathBuf};
+
+	let dir = env::var_os("XDG_CONFIG_HOME")
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+	Some(dir.join("web-bg").join("bindings.txt"))
+}
+
+/// Load the saved bindings text from the config file
+#[cfg(not(target_arch = "wasm32"))]
+fn load_saved() -> Option<String> {
+	std::fs::read_to_string(config_path()?).ok()
+}
+
+/// Save the bindings text in the config file
+#[cfg(not(target_arch = "wasm32"))]
+fn store_saved(text: &str) {
+	let Some(path) = config_path() else {
+		warn!("could not save bindings, no config directory found");
+		return;
+	};
+
+	let res = path
+		.parent()
+		.map_or(Ok(()), std::fs::create_dir_all)
+		.and_then(|()| std::fs::write(&path, text));
+
+	if let Err(e) = res {
+		warn!("could not save bindings to {}: {e}", path.display());
+	}
+}
+
+/// The state of all actions in the current and the previous frame
+#[derive(Debug, Clone, Default, Resource)]
+pub struct ActionState {
+	current: HashMap<Action, f32>,
+	previous: HashMap<Action, f32>,
+}
+
+impl ActionState {
+	/// Get how far `action` is pressed, from `0.0` to `1.0`
+	#[must_use]
+	pub fn value(&self, action: Action) -> f32 {
+		self.current.get(&action).copied().unwrap_or_default()
+	}
+
+	/// Whether `action` is pressed
+	#[must_use]
+	pub fn pressed(&self, action: Action) -> bool {
+		self.value(action) >= PRESS_THRESHOLD
+	}
+
+	/// Whether `action` started being pressed this frame
+	#[must_use]
+	pub fn just_pressed(&self, action: Action) -> bool {
+		self.pressed(action)
+			&& self.previous.get(&action).copied().unwrap_or_default() < PRESS_THRESHOLD
+	}
+
+	/// Override how far `action` is pressed in the current frame (e.g. in a
+	/// [replay](crate::recording))
+	pub fn set(&mut self, action: Action, value: f32) {
+		self.current.insert(action, value);
+	}
+}
+
+/// Update the [`ActionState`] from the raw inputs and the [`Bindings`]
+///
+/// This runs in `PreUpdate`, after Bevy's input processing
+pub fn update(
+	bindings: Res<Bindings>,
+	keys: Res<ButtonInput<KeyCode>>,
+	mouse: Res<ButtonInput<MouseButton>>,
+	gamepads: Query<&Gamepad>,
+	mut state: ResMut<ActionState>,
+) {
+	let current = Action::ALL
+		.into_iter()
+		.map(|action| {
+			let value = bindings
+				.get(action)
+				.iter()
+				.map(|binding| binding.value(&keys, &mouse, &gamepads))
+				.fold(0.0, f32::max);
+
+			(action, value)
+		})
+		.collect();
+
+	state.previous = std::mem::replace(&mut state.current, current);
+}
+
+/// Pause or resume the game when the [`Pause`](Action::Pause) action is
+/// pressed
+pub fn toggle_pause(actions: Res<ActionState>, mut pause: ResMut<Pause>) {
+	if !actions.just_pressed(Action::Pause) {
+		return;
+	}
+
+	if pause.reasons().contains(&PauseReason::User) {
+		pause.resume(PauseReason::User);
+	} else {
+		pause.pause(PauseReason::User);
+	}
+}
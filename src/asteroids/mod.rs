@@ -2,4 +2,21 @@
 
 use bevy::prelude::*;
 
-pub fn start(app: &mut App) {}
+use crate::games::{GameInfo, Minigame};
+
+/// The Asteroids minigame
+pub struct AsteroidsGame;
+
+impl Minigame for AsteroidsGame {
+	fn info(&self) -> &'static GameInfo {
+		&GameInfo {
+			name: "asteroids",
+			title: "Asteroids",
+			description: "An Asteroids-inspired space flying game.",
+			controls: &[],
+			attribution: &[],
+		}
+	}
+
+	fn build(&self, app: &mut App) {}
+}
@@ -0,0 +1,593 @@
+//! Controlling the running application from outside of it
+//!
+//! On the web, the [`Controller`] is exported to JS, so that the website can
+//! send commands to `web-bg` (e.g. `new Controller().pause()`) and query its
+//! status. Natively, the same [`Controller`] can be used from Rust. Commands
+//! are queued, then sent into the app as [`ControlCommand`] events at the
+//! start of the next frame, and handled by regular systems.
+
+use std::{
+	collections::{HashSet, VecDeque},
+	fmt::{Display, Formatter, Result as FmtResult},
+	str::FromStr,
+	sync::Mutex,
+};
+
+use bevy::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+
+use crate::{
+	actions::{self, Action, Binding, Bindings},
+	events,
+	games::{GameState, SwitchGame},
+	recording::{self, Recording},
+};
+
+/// Commands sent to the app which have not been received by it yet
+static QUEUE: Mutex<VecDeque<ControlCommand>> = Mutex::new(VecDeque::new());
+
+/// The status of the app as of the end of the last frame
+static STATUS: Mutex<Status> = Mutex::new(Status::INITIAL);
+
+/// A command for the running app
+#[derive(Debug, Clone, PartialEq, Event)]
+pub enum ControlCommand {
+	/// Pause the game for the given reason
+	Pause(PauseReason),
+	/// Resume the game (if it was paused for the given reason, and there is no
+	/// other reason for it to be paused)
+	Resume(PauseReason),
+	/// Change the graphics quality
+	SetQuality(Quality),
+	/// Switch to another game (see [`SwitchGame`])
+	SwitchGame(String),
+	/// Change (and save) the bindings of an action
+	Rebind(Action, Vec<Binding>),
+	/// Reset (and save) the bindings of all actions to the defaults
+	ResetBindings,
+	/// Restart the current game and start recording it (see
+	/// [`recording`](crate::recording))
+	StartRecording,
+	/// Stop recording
+	StopRecording,
+	/// Replay a recording
+	Replay(Recording),
+}
+
+/// A handle for controlling the running app
+///
+/// This is exported to JS as `Controller`
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Controller {
+	_private: (),
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+impl Controller {
+	/// Create a new `Controller`
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
+	#[allow(clippy::missing_const_for_fn)] // `wasm_bindgen` doesn't support `const fn`
+	#[must_use]
+	pub fn new() -> Self {
+		Self { _private: () }
+	}
+
+	/// Pause the game
+	pub fn pause(&self) {
+		send(ControlCommand::Pause(PauseReason::User));
+	}
+
+	/// Resume the game after it was paused with [`pause`](Controller::pause)
+	pub fn resume(&self) {
+		send(ControlCommand::Resume(PauseReason::User));
+	}
+
+	/// Set the graphics quality (`"low"` or `"high"`)
+	///
+	/// # Errors
+	/// This function returns an error if the quality is not valid
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = setQuality))]
+	pub fn set_quality(&self, quality: &str) -> Result<(), String> {
+		send(ControlCommand::SetQuality(quality.parse()?));
+		Ok(())
+	}
+
+	/// Switch to the game called `name`, or restart it if it's already running
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = switchGame))]
+	pub fn switch_game(&self, name: String) {
+		send(ControlCommand::SwitchGame(name));
+	}
+
+	/// Change the bindings of an action (e.g. `"sprint"`) to the given
+	/// comma-separated list of bindings (e.g. `"key:ShiftLeft, mouse:Right"`)
+	///
+	/// The new bindings are saved, so they are also used the next time
+	///
+	/// # Errors
+	/// This function returns an error if the action or any of the bindings is
+	/// not valid
+	pub fn rebind(&self, action: &str, bindings: &str) -> Result<(), String> {
+		send(ControlCommand::Rebind(
+			action.parse()?,
+			actions::parse_bindings(bindings)?,
+		));
+		Ok(())
+	}
+
+	/// Reset the bindings of all actions to the defaults
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = resetBindings))]
+	pub fn reset_bindings(&self) {
+		send(ControlCommand::ResetBindings);
+	}
+
+	/// Get the bindings of all actions, with one action per line
+	#[must_use]
+	pub fn bindings(&self) -> String {
+		status().bindings
+	}
+
+	/// Restart the current game and start recording it
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = startRecording))]
+	pub fn start_recording(&self) {
+		send(ControlCommand::StartRecording);
+	}
+
+	/// Stop recording
+	///
+	/// The recording is available from [`recording`](Controller::recording)
+	/// (and in the `web-bg-record` event) once it's finished, at the start of
+	/// the next frame
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = stopRecording))]
+	pub fn stop_recording(&self) {
+		send(ControlCommand::StopRecording);
+	}
+
+	/// Get the last finished recording, if there is one
+	#[must_use]
+	pub fn recording(&self) -> Option<Vec<u8>> {
+		recording::last_recording()
+	}
+
+	/// Replay a recording, restarting its game with its seed
+	///
+	/// # Errors
+	/// This function returns an error if the recording is not valid
+	pub fn replay(&self, recording: &[u8]) -> Result<(), String> {
+		send(ControlCommand::Replay(Recording::from_bytes(recording)?));
+		Ok(())
+	}
+
+	/// Get the name of the game which is currently running, if any
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = currentGame))]
+	#[must_use]
+	pub fn current_game(&self) -> Option<String> {
+		status().game.map(str::to_string)
+	}
+
+	/// Get the score in the current game, if it has one
+	#[must_use]
+	pub fn score(&self) -> Option<u32> {
+		status().score
+	}
+
+	/// Whether the game is currently paused (for any reason)
+	#[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = isPaused))]
+	#[must_use]
+	pub fn is_paused(&self) -> bool {
+		status().paused
+	}
+
+	/// Get the current graphics quality
+	#[must_use]
+	pub fn quality(&self) -> String {
+		status().quality.to_string()
+	}
+}
+
+/// Queue a command to be sent to the app
+///
+/// # Panics
+/// This function panics if the command queue's mutex is poisoned
+pub fn send(command: ControlCommand) {
+	QUEUE
+		.lock()
+		.expect("command queue mutex poisoned")
+		.push_back(command);
+}
+
+/// Get the status of the app as of the end of the last frame
+///
+/// # Panics
+/// This function panics if the status mutex is poisoned
+fn status() -> Status {
+	STATUS.lock().expect("status mutex poisoned").clone()
+}
+
+/// The app's status, as seen from outside of it
+#[derive(Debug, Clone)]
+struct Status {
+	game: Option<&'static str>,
+	score: Option<u32>,
+	paused: bool,
+	quality: Quality,
+	bindings: String,
+}
+
+impl Status {
+	const INITIAL: Self = Self {
+		game: None,
+		score: None,
+		paused: false,
+		quality: Quality::High,
+		bindings: String::new(),
+	};
+}
+
+/// Graphics quality
+///
+/// Games can adjust their graphics (e.g. turn off shadows) based on this
+/// resource
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Resource)]
+pub enum Quality {
+	Low,
+	#[default]
+	High,
+}
+
+impl FromStr for Quality {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"low" => Ok(Self::Low),
+			"high" => Ok(Self::High),
+			_ => Err(format!(
+				"invalid quality '{s}', expected \"low\" or \"high\""
+			)),
+		}
+	}
+}
+
+impl Display for Quality {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+		match self {
+			Self::Low => f.write_str("low"),
+			Self::High => f.write_str("high"),
+		}
+	}
+}
+
+/// The reason the game is paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PauseReason {
+	/// The user (or the website) paused the game
+	User,
+	/// The page (or the window) is hidden
+	Hidden,
+	/// The canvas is not on screen (e.g. it was scrolled out of view)
+	Offscreen,
+}
+
+impl PauseReason {
+	/// Get the name of this reason, as used in `web-bg-pause` events
+	#[must_use]
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::User => "user",
+			Self::Hidden => "hidden",
+			Self::Offscreen => "offscreen",
+		}
+	}
+}
+
+/// Whether (and why) the game is paused
+///
+/// The game is paused while there is at least one reason for it to be paused.
+/// While paused, virtual time doesn't advance, game systems using the
+/// [`running`] run condition don't run, and the app only updates in reaction
+/// to events (or about once a second).
+#[derive(Debug, Clone, Default, Resource)]
+pub struct Pause(HashSet<PauseReason>);
+
+impl Pause {
+	/// Pause the game for the given reason
+	pub fn pause(&mut self, reason: PauseReason) {
+		self.0.insert(reason);
+	}
+
+	/// Remove the given reason for the game to be paused
+	pub fn resume(&mut self, reason: PauseReason) {
+		self.0.remove(&reason);
+	}
+
+	/// Whether the game is paused (for any reason)
+	#[must_use]
+	pub fn is_paused(&self) -> bool {
+		!self.0.is_empty()
+	}
+
+	/// Get the reasons why the game is paused, sorted
+	#[must_use]
+	pub fn reasons(&self) -> Vec<PauseReason> {
+		let mut reasons = self.0.iter().copied().collect::<Vec<_>>();
+		reasons.sort_unstable();
+		reasons
+	}
+}
+
+/// A run condition for game systems, which should not run while the game is
+/// paused
+#[must_use]
+pub fn running(pause: Res<Pause>) -> bool {
+	!pause.is_paused()
+}
+
+/// The score in the current game
+///
+/// Games with a score should insert this resource when they start, keep it up
+/// to date, and remove it when they stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Resource)]
+pub struct Score(pub u32);
+
+/// The position of the primary player in the current game
+///
+/// Games with a player should insert this resource when they start, keep it up
+/// to date, and remove it when they stop
+#[derive(Debug, Clone, Copy, PartialEq, Default, Resource)]
+pub struct PlayerPosition(pub Vec2);
+
+/// Send queued commands into the app as [`ControlCommand`] events
+///
+/// # Panics
+/// This function panics if the command queue's mutex is poisoned
+pub fn receive(mut commands: EventWriter<ControlCommand>) {
+	let queued = QUEUE
+		.lock()
+		.expect("command queue mutex poisoned")
+		.drain(..)
+		.collect::<Vec<_>>();
+
+	commands.write_batch(queued);
+}
+
+/// Handle [`ControlCommand`]s
+pub fn handle(
+	mut commands: EventReader<ControlCommand>,
+	mut pause: ResMut<Pause>,
+	mut quality: ResMut<Quality>,
+	mut switches: EventWriter<SwitchGame>,
+	mut bindings: ResMut<Bindings>,
+) {
+	for command in commands.read() {
+		debug!("received command {command:?}");
+
+		match command {
+			ControlCommand::Pause(reason) => pause.pause(*reason),
+			ControlCommand::Resume(reason) => pause.resume(*reason),
+			ControlCommand::SetQuality(q) => *quality = *q,
+			ControlCommand::SwitchGame(name) => {
+				switches.write(SwitchGame(name.clone()));
+			}
+			ControlCommand::Rebind(action, list) => {
+				bindings.set(*action, list.clone());
+				bindings.save();
+			}
+			ControlCommand::ResetBindings => {
+				*bindings = Bindings::default();
+				bindings.save();
+			}
+			// Handled by `recording::handle`
+			ControlCommand::StartRecording
+			| ControlCommand::StopRecording
+			| ControlCommand::Replay(_) => (),
+		}
+	}
+}
+
+/// Apply the [`Pause`] when the game is paused or resumed
+///
+/// This pauses or unpauses virtual time, and dispatches the
+/// [`Paused`](events::RunEvent::Paused) and
+/// [`Resumed`](events::RunEvent::Resumed) events. The update rate is adjusted
+/// separately, by [`power::update_mode`](crate::power::update_mode).
+pub fn apply_pause(
+	pause: Res<Pause>,
+	mut time: ResMut<Time<Virtual>>,
+	mut was_paused: Local<bool>,
+) {
+	if !pause.is_changed() || pause.is_paused() == *was_paused {
+		return;
+	}
+
+	*was_paused = pause.is_paused();
+
+	if pause.is_paused() {
+		time.pause();
+		events::paused(pause.reasons().into_iter().map(PauseReason::name).collect());
+	} else {
+		time.unpause();
+		events::resumed();
+	}
+}
+
+/// Update the status returned by the [`Controller`]
+///
+/// # Panics
+/// This function panics if the status mutex is poisoned
+pub fn update_status(
+	state: Res<State<GameState>>,
+	score: Option<Res<Score>>,
+	pause: Res<Pause>,
+	quality: Res<Quality>,
+	bindings: Res<Bindings>,
+) {
+	let mut status = STATUS.lock().expect("status mutex poisoned");
+
+	status.game = match *state.get() {
+		GameState::None => None,
+		GameState::Running(name) => Some(name),
+	};
+	status.score = score.map(|s| s.0);
+	status.paused = pause.is_paused();
+	status.quality = *quality;
+
+	// Only re-format the bindings when they change
+	if bindings.is_changed() {
+		status.bindings = bindings.to_string();
+	}
+}
+
+#[cfg(test)]
+pub mod tests {
+	use std::sync::{MutexGuard, PoisonError};
+
+	use bevy::state::app::StatesPlugin;
+
+	use super::*;
+
+	/// Held by tests which use the global command queue and status
+	static LOCK: Mutex<()> = Mutex::new(());
+
+	/// Get exclusive access to the global command queue and status, starting
+	/// with an empty queue
+	///
+	/// # Panics
+	/// This function panics if the command queue's mutex is poisoned
+	pub fn lock() -> MutexGuard<'static, ()> {
+		let guard = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+		QUEUE.lock().expect("command queue mutex poisoned").clear();
+		guard
+	}
+
+	/// Create an app with only the control systems, with the maze running
+	fn app() -> App {
+		let mut app = App::new();
+
+		app.add_plugins((MinimalPlugins, StatesPlugin))
+			.insert_state(GameState::Running("maze"))
+			.add_event::<SwitchGame>()
+			.init_resource::<Pause>()
+			.init_resource::<Quality>()
+			.insert_resource(Bindings::default())
+			.add_event::<ControlCommand>()
+			.add_systems(PreUpdate, receive)
+			.add_systems(Update, (handle, apply_pause).chain())
+			.add_systems(Last, update_status);
+
+		app
+	}
+
+	#[test]
+	fn pause_and_resume() {
+		let _lock = lock();
+		let mut app = app();
+		let controller = Controller::new();
+
+		controller.pause();
+		app.update();
+
+		assert!(app.world().resource::<Pause>().is_paused());
+		assert!(app.world().resource::<Time<Virtual>>().is_paused());
+		assert!(controller.is_paused());
+
+		// Resuming for another reason doesn't resume the game
+		send(ControlCommand::Resume(PauseReason::Hidden));
+		app.update();
+
+		assert!(controller.is_paused());
+
+		controller.resume();
+		app.update();
+
+		assert!(!app.world().resource::<Pause>().is_paused());
+		assert!(!app.world().resource::<Time<Virtual>>().is_paused());
+		assert!(!controller.is_paused());
+	}
+
+	#[test]
+	fn pause_for_several_reasons() {
+		let _lock = lock();
+		let mut app = app();
+
+		send(ControlCommand::Pause(PauseReason::User));
+		send(ControlCommand::Pause(PauseReason::Offscreen));
+		app.update();
+
+		assert_eq!(app.world().resource::<Pause>().reasons(), [
+			PauseReason::User,
+			PauseReason::Offscreen
+		]);
+
+		send(ControlCommand::Resume(PauseReason::User));
+		app.update();
+
+		assert_eq!(app.world().resource::<Pause>().reasons(), [
+			PauseReason::Offscreen
+		]);
+		assert!(Controller::new().is_paused());
+	}
+
+	#[test]
+	fn quality() {
+		let _lock = lock();
+		let mut app = app();
+		let controller = Controller::new();
+
+		assert_eq!(
+			controller.set_quality("medium"),
+			Err("invalid quality 'medium', expected \"low\" or \"high\"".to_string())
+		);
+		app.update();
+
+		assert_eq!(*app.world().resource::<Quality>(), Quality::High);
+		assert_eq!(controller.quality(), "high");
+
+		controller.set_quality("low").unwrap();
+		app.update();
+
+		assert_eq!(*app.world().resource::<Quality>(), Quality::Low);
+		assert_eq!(controller.quality(), "low");
+	}
+
+	#[test]
+	fn status() {
+		let _lock = lock();
+		let mut app = app();
+		let controller = Controller::new();
+
+		app.update();
+
+		assert_eq!(controller.current_game().as_deref(), Some("maze"));
+		assert_eq!(controller.score(), None);
+		assert_eq!(controller.bindings(), Bindings::default().to_string());
+
+		app.insert_resource(Score(3));
+		app.update();
+
+		assert_eq!(controller.score(), Some(3));
+
+		app.world_mut().resource_mut::<Score>().0 = 4;
+		app.update();
+
+		assert_eq!(controller.score(), Some(4));
+	}
+
+	#[test]
+	fn switch_game() {
+		let _lock = lock();
+		let mut app = app();
+
+		Controller::new().switch_game("maze".to_string());
+		app.update();
+
+		let switches = app.world().resource::<Events<SwitchGame>>();
+		let names = switches
+			.iter_current_update_events()
+			.map(|switch| switch.0.as_str())
+			.collect::<Vec<_>>();
+
+		assert_eq!(names, ["maze"]);
+	}
+}
@@ -16,14 +16,32 @@ use web_sys::{CustomEvent, CustomEventInit, Event};
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
+#[cfg(target_arch = "wasm32")]
+use crate::games::GameInfo;
+#[cfg(target_arch = "wasm32")]
+use crate::recording::Outcome;
+use crate::recording::ReplayResult;
+
 static STARTUP_TIME: OnceLock<Instant> = OnceLock::new();
 
+/// Information about the application being loaded
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadInfo {
+	/// The name of the game which is being loaded
+	pub game: &'static str,
+	/// The name of the game that was requested (via parameters), if it was not
+	/// found among the compiled-in games
+	pub unknown_game: Option<String>,
+	/// The seed used for all random number generation
+	pub seed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum RunEvent {
 	/// The application loaded (near the beginning of `main`)
 	///
 	/// Contains the name of the game which is being loaded
-	Loaded(&'static str),
+	Loaded(LoadInfo),
 	/// The application has initialized (after the startup systems have run)
 	///
 	/// Contains the time since application startup
@@ -39,6 +57,25 @@ pub enum RunEvent {
 	///
 	/// This event is also dispatched on wasm if `main` returns
 	Panicked(Option<String>),
+	/// The application switched to another game (or restarted the current one),
+	/// which is now running
+	///
+	/// Contains the name of the game which is now running
+	Switched(&'static str),
+	/// The game was paused (e.g. because the page is hidden)
+	///
+	/// Contains the reasons why the game is paused
+	Paused(Vec<&'static str>),
+	/// The game was resumed after being paused
+	Resumed,
+	/// A recording was finished
+	///
+	/// Contains the serialized recording
+	Recorded(Vec<u8>),
+	/// A replay was finished
+	///
+	/// Contains the outcomes of the replay and the recorded session
+	Replayed(ReplayResult),
 }
 
 impl RunEvent {
@@ -52,6 +89,11 @@ impl RunEvent {
 			Self::Initialized(_) => "web-bg-init",
 			Self::Started(_) => "web-bg-start",
 			Self::Panicked(_) => "web-bg-panic",
+			Self::Switched(_) => "web-bg-switch",
+			Self::Paused(_) => "web-bg-pause",
+			Self::Resumed => "web-bg-resume",
+			Self::Recorded(_) => "web-bg-record",
+			Self::Replayed(_) => "web-bg-replay",
 		}
 	}
 
@@ -61,12 +103,48 @@ impl RunEvent {
 	#[must_use]
 	pub fn details(&self) -> JsValue {
 		match self {
-			Self::Loaded(s) => JsValue::from_str(s),
-			Self::Initialized(None) | Self::Started(None) | Self::Panicked(None) => JsValue::null(),
+			Self::Loaded(info) => {
+				let obj = js_sys::Object::new();
+				set(&obj, "game", &JsValue::from_str(info.game));
+				set(
+					&obj,
+					"unknownGame",
+					&info
+						.unknown_game
+						.as_deref()
+						.map_or(JsValue::null(), JsValue::from_str),
+				);
+				// As a string, because JS numbers can't represent all `u64`s
+				set(&obj, "seed", &JsValue::from_str(&info.seed.to_string()));
+				let games = crate::games::all()
+					.map(game_info_js)
+					.collect::<js_sys::Array>();
+				set(&obj, "games", &games);
+				obj.into()
+			}
+			Self::Initialized(None)
+			| Self::Started(None)
+			| Self::Panicked(None)
+			| Self::Resumed => JsValue::null(),
 			Self::Initialized(Some(d)) | Self::Started(Some(d)) => {
 				JsValue::from_f64(d.as_secs_f64())
 			}
 			Self::Panicked(Some(s)) => JsValue::from_str(s),
+			Self::Switched(game) => JsValue::from_str(game),
+			Self::Paused(reasons) => reasons
+				.iter()
+				.copied()
+				.map(JsValue::from_str)
+				.collect::<js_sys::Array>()
+				.into(),
+			Self::Recorded(bytes) => js_sys::Uint8Array::from(bytes.as_slice()).into(),
+			Self::Replayed(result) => {
+				let obj = js_sys::Object::new();
+				set(&obj, "matches", &JsValue::from_bool(result.matches()));
+				set(&obj, "outcome", &outcome_js(result.outcome));
+				set(&obj, "expected", &outcome_js(result.expected));
+				obj.into()
+			}
 		}
 	}
 
@@ -90,7 +168,21 @@ impl RunEvent {
 impl Display for RunEvent {
 	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
 		match self {
-			Self::Loaded(game) => f.write_fmt(format_args!("`web-bg` loaded, starting '{game}'")),
+			Self::Loaded(LoadInfo {
+				game,
+				unknown_game: None,
+				seed,
+			}) => f.write_fmt(format_args!(
+				"`web-bg` loaded, starting '{game}' with seed {seed}"
+			)),
+			Self::Loaded(LoadInfo {
+				game,
+				unknown_game: Some(unknown),
+				seed,
+			}) => f.write_fmt(format_args!(
+				"`web-bg` loaded, starting '{game}' with seed {seed} (unknown game '{unknown}' \
+				 was requested)"
+			)),
 			Self::Initialized(None) => f.write_str("`web-bg` initialized"),
 			Self::Initialized(Some(d)) => {
 				f.write_fmt(format_args!("`web-bg` initialized in {} ms", d.as_millis()))
@@ -101,10 +193,105 @@ impl Display for RunEvent {
 			}
 			Self::Panicked(None) => f.write_str("`web-bg` panicked"),
 			Self::Panicked(Some(d)) => f.write_fmt(format_args!("`web-bg` panicked:\n{d}")),
+			Self::Switched(game) => f.write_fmt(format_args!("`web-bg` switched to '{game}'")),
+			Self::Paused(reasons) => {
+				f.write_fmt(format_args!("`web-bg` paused ({})", reasons.join(", ")))
+			}
+			Self::Resumed => f.write_str("`web-bg` resumed"),
+			Self::Recorded(bytes) => f.write_fmt(format_args!(
+				"`web-bg` finished recording ({} bytes)",
+				bytes.len()
+			)),
+			Self::Replayed(result) if result.matches() => f.write_fmt(format_args!(
+				"`web-bg` finished replaying, the outcome matches ({:?})",
+				result.outcome
+			)),
+			Self::Replayed(result) => f.write_fmt(format_args!(
+				"`web-bg` finished replaying, the outcome doesn't match (got {:?}, expected {:?})",
+				result.outcome, result.expected
+			)),
 		}
 	}
 }
 
+/// Set the property `key` of the JS object `obj` to `value`
+///
+/// # Panics
+/// This function panics if setting the property throws
+#[cfg(target_arch = "wasm32")]
+fn set(obj: &js_sys::Object, key: &str, value: &JsValue) {
+	js_sys::Reflect::set(obj, &JsValue::from_str(key), value).expect("JS `Reflect.set` failed");
+}
+
+/// Get a JS object representing the metadata of a minigame
+#[cfg(target_arch = "wasm32")]
+fn game_info_js(info: &GameInfo) -> JsValue {
+	let obj = js_sys::Object::new();
+	set(&obj, "name", &JsValue::from_str(info.name));
+	set(&obj, "title", &JsValue::from_str(info.title));
+	set(&obj, "description", &JsValue::from_str(info.description));
+
+	let controls = info
+		.controls
+		.iter()
+		.map(|control| {
+			let obj = js_sys::Object::new();
+			set(&obj, "action", &JsValue::from_str(control.action));
+			set(&obj, "inputs", &JsValue::from_str(control.inputs));
+			JsValue::from(obj)
+		})
+		.collect::<js_sys::Array>();
+	set(&obj, "controls", &controls);
+
+	let attribution = info
+		.attribution
+		.iter()
+		.map(|attribution| {
+			let obj = js_sys::Object::new();
+			set(&obj, "usage", &JsValue::from_str(attribution.usage));
+			set(&obj, "title", &JsValue::from_str(attribution.title));
+			set(&obj, "author", &JsValue::from_str(attribution.author));
+			set(&obj, "url", &JsValue::from_str(attribution.url));
+			let files = attribution
+				.files
+				.iter()
+				.copied()
+				.map(JsValue::from_str)
+				.collect::<js_sys::Array>();
+			set(&obj, "files", &files);
+			JsValue::from(obj)
+		})
+		.collect::<js_sys::Array>();
+	set(&obj, "attribution", &attribution);
+
+	obj.into()
+}
+
+/// Get a JS object representing the outcome of a recorded or replayed session
+#[cfg(target_arch = "wasm32")]
+fn outcome_js(outcome: Outcome) -> JsValue {
+	let obj = js_sys::Object::new();
+	set(
+		&obj,
+		"score",
+		&outcome
+			.score
+			.map_or(JsValue::null(), |s| JsValue::from_f64(s.into())),
+	);
+	set(
+		&obj,
+		"position",
+		&outcome.position.map_or(JsValue::null(), |p| {
+			[p.x, p.y]
+				.into_iter()
+				.map(|c| JsValue::from_f64(c.into()))
+				.collect::<js_sys::Array>()
+				.into()
+		}),
+	);
+	obj.into()
+}
+
 /// Initialize event time measurements
 ///
 /// This should be called as early as possible during application startup
@@ -119,11 +306,11 @@ pub fn init() {
 /// # Panics
 /// This function panics on `wasm32` if the `window` JS object or its
 /// `dispatchEvent` method are unavailable or throw
-pub fn loaded(game: &'static str) {
+pub fn loaded(info: LoadInfo) {
 	static ONCE: Once = Once::new();
 
 	ONCE.call_once(|| {
-		let event = RunEvent::Loaded(game);
+		let event = RunEvent::Loaded(info);
 
 		#[cfg(target_arch = "wasm32")]
 		web_sys::window()
@@ -191,6 +378,63 @@ pub fn started() {
 	SKIP.call_once(|| ());
 }
 
+/// Dispatch the `Switched` event
+///
+/// This should be called when a game is running after switching games
+///
+/// # Panics
+/// This function panics on `wasm32` if the `window` JS object or its
+/// `dispatchEvent` method are unavailable or throw
+pub fn switched(game: &'static str) {
+	let event = RunEvent::Switched(game);
+
+	#[cfg(target_arch = "wasm32")]
+	web_sys::window()
+		.expect("JS `window` not available")
+		.dispatch_event(&event.into_js())
+		.expect("JS `dispatchEvent` failed");
+
+	info!("{event}");
+}
+
+/// Dispatch the `Paused` event
+///
+/// This should be called when the game is paused
+///
+/// # Panics
+/// This function panics on `wasm32` if the `window` JS object or its
+/// `dispatchEvent` method are unavailable or throw
+pub fn paused(reasons: Vec<&'static str>) {
+	let event = RunEvent::Paused(reasons);
+
+	#[cfg(target_arch = "wasm32")]
+	web_sys::window()
+		.expect("JS `window` not available")
+		.dispatch_event(&event.into_js())
+		.expect("JS `dispatchEvent` failed");
+
+	info!("{event}");
+}
+
+/// Dispatch the `Resumed` event
+///
+/// This should be called when the game is resumed after being paused
+///
+/// # Panics
+/// This function panics on `wasm32` if the `window` JS object or its
+/// `dispatchEvent` method are unavailable or throw
+pub fn resumed() {
+	let event = RunEvent::Resumed;
+
+	#[cfg(target_arch = "wasm32")]
+	web_sys::window()
+		.expect("JS `window` not available")
+		.dispatch_event(&event.into_js())
+		.expect("JS `dispatchEvent` failed");
+
+	info!("{event}");
+}
+
 /// Dispatch the `Panicked` event
 ///
 /// This should be called from a panic handler or hook
@@ -209,3 +453,45 @@ pub fn panic(info: String) {
 
 	error!("{event}");
 }
+
+/// Dispatch the `Recorded` event
+///
+/// This should be called when a recording is finished
+///
+/// # Panics
+/// This function panics on `wasm32` if the `window` JS object or its
+/// `dispatchEvent` method are unavailable or throw
+pub fn recorded(bytes: Vec<u8>) {
+	let event = RunEvent::Recorded(bytes);
+
+	#[cfg(target_arch = "wasm32")]
+	web_sys::window()
+		.expect("JS `window` not available")
+		.dispatch_event(&event.into_js())
+		.expect("JS `dispatchEvent` failed");
+
+	info!("{event}");
+}
+
+/// Dispatch the `Replayed` event
+///
+/// This should be called when a replay is finished
+///
+/// # Panics
+/// This function panics on `wasm32` if the `window` JS object or its
+/// `dispatchEvent` method are unavailable or throw
+pub fn replayed(result: ReplayResult) {
+	let event = RunEvent::Replayed(result);
+
+	#[cfg(target_arch = "wasm32")]
+	web_sys::window()
+		.expect("JS `window` not available")
+		.dispatch_event(&event.into_js())
+		.expect("JS `dispatchEvent` failed");
+
+	if result.matches() {
+		info!("{event}");
+	} else {
+		warn!("{event}");
+	}
+}
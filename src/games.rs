@@ -0,0 +1,177 @@
+//! The minigame registry, minigame metadata, and the minigame lifecycle
+
+use bevy::prelude::*;
+
+use crate::events;
+
+/// All compiled-in minigames
+///
+/// Each minigame is only included if its Cargo feature is enabled
+pub static GAMES: &[&dyn Minigame] = &[
+	// #[cfg(feature = "asteroids")]
+	// &crate::asteroids::AsteroidsGame,
+	// #[cfg(feature = "lander")]
+	// &crate::lander::LanderGame,
+	// #[cfg(feature = "mapgen")]
+	// &crate::mapgen::MapgenGame,
+	// #[cfg(feature = "mapman")]
+	// &crate::mapman::MapmanGame,
+	#[cfg(feature = "maze")]
+	&crate::maze::MazeGame,
+	// #[cfg(feature = "portoom")]
+	// &crate::portoom::PortoomGame,
+	// #[cfg(feature = "racecar")]
+	// &crate::racecar::RacecarGame,
+];
+
+/// A minigame/background
+pub trait Minigame: Sync {
+	/// Get this minigame's metadata
+	fn info(&self) -> &'static GameInfo;
+
+	/// Add this minigame's systems to the app
+	///
+	/// This is called once for every compiled-in minigame when the app is
+	/// created. Systems should only run while the minigame is running (i.e. in
+	/// this minigame's [`state`](Minigame::state)). Setup should happen in
+	/// `OnEnter`, and everything the minigame creates must be removed when it
+	/// stops (entities should be [`StateScoped`], resources should be removed
+	/// in `OnExit`), so that another minigame can be started afterwards.
+	fn build(&self, app: &mut App);
+
+	/// Get the [`GameState`] in which this minigame is running
+	fn state(&self) -> GameState {
+		GameState::Running(self.info().name)
+	}
+}
+
+/// The lifecycle state of minigames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, States)]
+pub enum GameState {
+	/// No minigame is running (e.g. in between stopping one minigame and
+	/// starting another)
+	#[default]
+	None,
+	/// The minigame with this name is running
+	Running(&'static str),
+}
+
+/// A request to stop the current minigame and start the one with the given
+/// name instead
+///
+/// If the requested minigame is already running, it is restarted
+#[derive(Debug, Clone, PartialEq, Eq, Event)]
+pub struct SwitchGame(pub String);
+
+/// Handle [`SwitchGame`] requests by changing the [`GameState`]
+///
+/// Restarting the current minigame goes through [`GameState::None`] for one
+/// frame, so that it is stopped and started again
+pub fn switch(
+	mut switches: EventReader<SwitchGame>,
+	state: Res<State<GameState>>,
+	mut next_state: ResMut<NextState<GameState>>,
+	mut restarting: Local<Option<GameState>>,
+) {
+	if let Some(restarted) = restarting.take() {
+		next_state.set(restarted);
+		return;
+	}
+
+	let Some(SwitchGame(name)) = switches.read().last() else {
+		return;
+	};
+
+	let Some(game) = find(name) else {
+		warn!("can't switch to unknown game '{name}'");
+		return;
+	};
+
+	if *state.get() == game.state() {
+		*restarting = Some(game.state());
+		next_state.set(GameState::None);
+	} else {
+		next_state.set(game.state());
+	}
+}
+
+/// Dispatch the [`Switched`](events::RunEvent::Switched) event when a minigame
+/// starts running after a switch
+///
+/// # Panics
+/// This function panics if the running minigame is not compiled in
+pub fn switched(
+	mut transitions: EventReader<StateTransitionEvent<GameState>>,
+	#[cfg(not(target_arch = "wasm32"))] mut windows: Query<&mut Window>,
+) {
+	for transition in transitions.read() {
+		// The initial transition (when the app starts) is not a switch
+		if transition.exited.is_none() {
+			continue;
+		}
+
+		if let Some(GameState::Running(name)) = transition.entered {
+			let game = find(name).expect("running game not found");
+
+			#[cfg(not(target_arch = "wasm32"))]
+			for mut window in &mut windows {
+				window.title = format!("{} | web-bg", game.info().title);
+			}
+
+			events::switched(game.info().name);
+		}
+	}
+}
+
+/// Metadata about a minigame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameInfo {
+	/// The minigame's name, the same as its Cargo feature (e.g. `"maze"`)
+	pub name: &'static str,
+	/// The human-readable title of the minigame (e.g. `"Maze Cave"`)
+	pub title: &'static str,
+	/// A short description of the minigame
+	pub description: &'static str,
+	/// How the minigame is controlled
+	pub controls: &'static [Control],
+	/// Attribution for assets used by the minigame
+	pub attribution: &'static [Attribution],
+}
+
+/// A minigame control (an action and the inputs which perform it)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Control {
+	/// What this control does (e.g. `"Move"`)
+	pub action: &'static str,
+	/// The inputs for this control (e.g. `"WASD or arrow keys"`)
+	pub inputs: &'static str,
+}
+
+/// Attribution for an asset (or a group of assets) used in a minigame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Attribution {
+	/// What the asset is used for (e.g. `"Player character"`)
+	pub usage: &'static str,
+	/// The title of the original work
+	pub title: &'static str,
+	/// The author of the original work
+	pub author: &'static str,
+	/// Where the original work can be found
+	pub url: &'static str,
+	/// The asset files based on the original work, relative to `assets/`
+	pub files: &'static [&'static str],
+}
+
+/// Find the compiled-in minigame called `name`
+#[must_use]
+pub fn find(name: &str) -> Option<&'static dyn Minigame> {
+	GAMES.iter().copied().find(|game| game.info().name == name)
+}
+
+/// Get the metadata of all compiled-in minigames
+pub fn all() -> impl Iterator<Item = &'static GameInfo> {
+	GAMES.iter().map(|game| game.info())
+}
+
+#[cfg(not(any(feature = "maze")))]
+compile_error!("At least one minigame must be enabled");
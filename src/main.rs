@@ -6,33 +6,62 @@
 #![allow(clippy::module_name_repetitions)]
 #![allow(clippy::tabs_in_doc_comments)]
 
+pub mod actions;
+pub mod control;
 pub mod events;
+pub mod games;
+pub mod params;
+pub mod power;
+pub mod recording;
+pub mod simulation;
 pub mod util;
+pub mod visibility;
+
+// #[cfg(feature = "asteroids")]
+// mod asteroids;
+// #[cfg(feature = "lander")]
+// mod lander;
+// #[cfg(feature = "mapgen")]
+// mod mapgen;
+// #[cfg(feature = "mapman")]
+// mod mapman;
+#[cfg(feature = "maze")]
+mod maze;
+// #[cfg(feature = "portoom")]
+// mod portoom;
+// #[cfg(feature = "racecar")]
+// mod racecar;
 
 use std::{
 	backtrace::{Backtrace, BacktraceStatus},
 	panic::PanicHookInfo,
 };
 
+use actions::{ActionState, Bindings};
 #[cfg(feature = "debug")]
 use bevy::{
 	diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
 	log::Level,
 };
-use bevy::{log::LogPlugin, prelude::*, window::WindowMode};
+use bevy::{input::InputSystem, log::LogPlugin, prelude::*, window::WindowMode};
 use bevy_embedded_assets::{EmbeddedAssetPlugin, PluginMode};
 use bevy_light_2d::plugin::Light2dPlugin;
 #[cfg(feature = "debug")]
 use bevy_screen_diagnostics::{
 	ScreenDiagnosticsPlugin, ScreenEntityDiagnosticsPlugin, ScreenFrameDiagnosticsPlugin,
 };
+use control::{ControlCommand, Pause, Quality};
+use events::LoadInfo;
+use games::{GAMES, GameState, Minigame, SwitchGame};
+use power::Activity;
+use recording::Recording;
 #[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
 use rlsf::SmallGlobalTlsf;
 #[cfg(all(feature = "console_log", target_arch = "wasm32"))]
 use tracing_subscriber::{fmt::format::Pretty, prelude::*};
 #[cfg(all(feature = "console_log", target_arch = "wasm32"))]
 use tracing_web::{MakeConsoleWriter, performance_layer};
-use util::{Rand, TurboRand};
+use util::{PlayerInputSet, Rand, TurboRand};
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
@@ -40,16 +69,6 @@ use wasm_bindgen::prelude::*;
 #[global_allocator]
 static ALLOC: SmallGlobalTlsf = SmallGlobalTlsf::new();
 
-games! {
-	// "asteroids" => asteroids,
-	// "lander" => lander,
-	// "mapgen" => mapgen,
-	// "mapman" => mapman,
-	"maze" => maze,
-	// "portoom" => portoom,
-	// "racecar" => racecar,
-}
-
 fn panic_hook(panic_info: &PanicHookInfo<'_>) {
 	#[cfg(target_arch = "wasm32")]
 	#[wasm_bindgen]
@@ -87,6 +106,88 @@ fn panic_hook(panic_info: &PanicHookInfo<'_>) {
 	events::panic(msg);
 }
 
+/// Choose the game to run
+///
+/// The game can be chosen by name (`?game=...`, `data-game="..."`, or
+/// `--game ...`/the first positional argument natively), or by the replayed
+/// recording, otherwise (or if there is no game with that name) it is picked at
+/// random. If the requested game was not found, its name is also returned.
+fn choose_game(rng: &Rand, replay: Option<&Recording>) -> (&'static dyn Minigame, Option<String>) {
+	let requested_game = replay
+		.map(|r| r.game.clone())
+		.or_else(|| params::get("game").or_else(|| params::positional().first().cloned()));
+	let game = requested_game.as_deref().and_then(games::find);
+	let unknown_game = requested_game.filter(|_| game.is_none());
+	let game = game.unwrap_or_else(|| {
+		*rng.substream("game-selection")
+			.sample(GAMES)
+			.expect("there are no games")
+	});
+
+	(game, unknown_game)
+}
+
+/// Add the systems and resources which manage the game's lifecycle (switching,
+/// control, input actions, pausing, and power saving), starting with `game`
+fn add_lifecycle_systems(app: &mut App, game: &dyn Minigame) {
+	app.insert_state(game.state())
+		.enable_state_scoped_entities::<GameState>()
+		.add_event::<SwitchGame>()
+		.add_systems(Update, (games::switch, games::switched));
+
+	app.init_resource::<Pause>()
+		.init_resource::<Quality>()
+		.add_event::<ControlCommand>()
+		.add_systems(PreUpdate, control::receive)
+		.add_systems(
+			Update,
+			(control::handle, control::apply_pause)
+				.chain()
+				.before(games::switch),
+		)
+		.add_systems(Last, control::update_status);
+
+	app.insert_resource(Bindings::load())
+		.init_resource::<ActionState>()
+		.add_systems(
+			PreUpdate,
+			(
+				actions::update,
+				util::update_input_slots.run_if(not(recording::replaying)),
+			)
+				.chain()
+				.after(InputSystem),
+		)
+		.configure_sets(
+			PreUpdate,
+			PlayerInputSet
+				.after(util::update_input_slots)
+				.run_if(not(recording::replaying)),
+		)
+		.add_systems(
+			Update,
+			actions::toggle_pause
+				.after(control::handle)
+				.before(control::apply_pause),
+		);
+
+	app.init_resource::<Activity>().add_systems(
+		Update,
+		(power::track_activity, power::update_mode)
+			.chain()
+			.after(control::apply_pause),
+	);
+
+	#[cfg(target_arch = "wasm32")]
+	visibility::init();
+
+	#[cfg(not(target_arch = "wasm32"))]
+	app.add_systems(
+		Update,
+		visibility::window_occlusion.before(control::apply_pause),
+	);
+}
+
 #[bevy_main]
 #[allow(clippy::missing_panics_doc)]
 pub fn main() {
@@ -109,10 +210,18 @@ pub fn main() {
 
 	events::init();
 
-	let rng = Rand::new();
-	let game = rng.sample(GAMES).expect("there are no games");
+	// A replay determines the seed and the game
+	let replay = Recording::from_params();
+	let rng = replay
+		.as_ref()
+		.map_or_else(Rand::from_params, |r| Rand::with_seed(r.seed));
+	let (game, unknown_game) = choose_game(&rng, replay.as_ref());
 
-	events::loaded(game.name);
+	events::loaded(LoadInfo {
+		game: game.info().name,
+		unknown_game,
+		seed: rng.seed(),
+	});
 
 	let mut app = App::new();
 
@@ -125,7 +234,7 @@ pub fn main() {
 				title: if cfg!(target_arch = "wasm32") {
 					String::new()
 				} else {
-					format!("{} | web-bg", game.name)
+					format!("{} | web-bg", game.info().title)
 				},
 				..default()
 			}),
@@ -164,7 +273,22 @@ pub fn main() {
 	app.add_systems(PostStartup, events::initialized);
 	app.add_systems(Update, events::started);
 
-	(game.start)(&mut app);
+	add_lifecycle_systems(&mut app, game);
+	recording::build(&mut app, replay);
+	simulation::build(&mut app);
+
+	for game in GAMES {
+		game.build(&mut app);
+	}
+
+	let exit = app.run();
 
-	app.run();
+	// Natively, report errors (e.g. from a replay) with the exit code
+	#[cfg(not(target_arch = "wasm32"))]
+	if let AppExit::Error(code) = exit {
+		std::process::exit(code.get().into());
+	}
+
+	#[cfg(target_arch = "wasm32")]
+	let _ = exit;
 }
@@ -0,0 +1,230 @@
+//! An "attract mode" for when nobody is playing
+//!
+//! After some time without input from any player, the autopilot takes over and
+//! walks the primary player through the maze towards the nearest food. It only
+//! generates (synthetic) [`PlayerInput`], so the player's movement, collision,
+//! animation, and eating work the same as with a real player. Real input takes
+//! back control immediately.
+//!
+//! The delay can be configured with the `autopilot` parameter (in seconds, or
+//! `off` to disable the autopilot).
+
+use std::{
+	collections::{HashMap, VecDeque, hash_map::Entry},
+	time::Duration,
+};
+
+use bevy::prelude::*;
+
+use super::{
+	maze::{Direction, Maze, TileChanged},
+	player::Player,
+};
+use crate::{
+	params,
+	simulation::Position,
+	util::{InputSlot, PlayerInput},
+};
+
+/// The default time without input until the autopilot takes over
+const DEFAULT_DELAY: Duration = Duration::from_secs(15);
+
+/// How close the player has to get to a waypoint to reach it
+const WAYPOINT_RADIUS: f32 = 8.0;
+
+/// The distance from a waypoint at which the autopilot starts slowing down
+const SLOWDOWN_DISTANCE: f32 = 10.0;
+
+/// The autopilot's state
+#[derive(Debug, Clone, Resource)]
+pub struct Autopilot {
+	/// How long without input until the autopilot takes over, if ever
+	delay: Option<Duration>,
+	/// The (virtual) time of the last real input
+	last_input: Duration,
+	/// The cells to walk through (see [`Maze`]), ending at a tile with food
+	path: VecDeque<IVec2>,
+	/// The cell from which no food could be reached, if the last search failed
+	///
+	/// The search isn't repeated (and the player stands still) until the player
+	/// is in another cell or a tile changes.
+	no_food_from: Option<IVec2>,
+}
+
+impl Autopilot {
+	/// Create a new `Autopilot`, with the delay from the `autopilot` parameter
+	#[must_use]
+	pub fn from_params() -> Self {
+		let delay = match params::get("autopilot").as_deref() {
+			None => Some(DEFAULT_DELAY),
+			Some("off") => None,
+			Some(secs) => {
+				let delay = secs
+					.parse()
+					.ok()
+					.and_then(|s| Duration::try_from_secs_f64(s).ok());
+
+				if delay.is_none() {
+					warn!("invalid autopilot delay '{secs}', using the default");
+				}
+
+				Some(delay.unwrap_or(DEFAULT_DELAY))
+			}
+		};
+
+		Self {
+			delay,
+			last_input: Duration::ZERO,
+			path: VecDeque::new(),
+			no_food_from: None,
+		}
+	}
+
+	/// Move the path by `offset` cells (e.g. when the maze is recentered)
+	pub fn shift(&mut self, offset: IVec2) {
+		for cell in self.path.iter_mut().chain(&mut self.no_food_from) {
+			*cell += offset;
+		}
+	}
+}
+
+pub fn initialize(mut commands: Commands) {
+	commands.insert_resource(Autopilot::from_params());
+}
+
+/// Drive the player towards the nearest food while nobody is playing
+///
+/// This must run after the [`input`](crate::util::input) system, and
+/// overwrites the primary player's (empty) input with synthetic input while it
+/// is active
+#[allow(clippy::type_complexity)]
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn drive(
+	time: Res<Time>,
+	maze: Res<Maze>,
+	mut autopilot: ResMut<Autopilot>,
+	mut slots: Query<(&InputSlot, &mut PlayerInput)>,
+	players: Query<(&Position, &Player)>,
+	mut changes: EventReader<TileChanged>,
+) {
+	// Find new food if the food at the end of the path was eaten by someone
+	// else, and search again if no food could be found before
+	for change in changes.read() {
+		autopilot.no_food_from = None;
+
+		let end = autopilot.path.back().and_then(|&cell| maze.tile_pos(cell));
+
+		if end == Some(change.pos) && !change.tile.has_food() {
+			autopilot.path.clear();
+		}
+	}
+
+	if slots.iter().any(|(_, input)| input.is_moving()) {
+		autopilot.last_input = time.elapsed();
+		autopilot.path.clear();
+		return;
+	}
+
+	let Some(delay) = autopilot.delay else {
+		return;
+	};
+
+	if time.elapsed().saturating_sub(autopilot.last_input) < delay {
+		return;
+	}
+
+	// The autopilot controls the primary player
+	let Some((_, mut input)) = slots.iter_mut().find(|(slot, _)| slot.is_primary()) else {
+		return;
+	};
+
+	let Some((player, _)) = players.iter().find(|(_, p)| p.number == 0) else {
+		return;
+	};
+
+	let loc = player.current;
+	let cell = maze.cell_at(loc);
+
+	if autopilot.path.is_empty() && autopilot.no_food_from != Some(cell) {
+		autopilot.path = path_to_food(&maze, cell);
+		autopilot.no_food_from = autopilot.path.is_empty().then_some(cell);
+	}
+
+	while let Some(&next) = autopilot.path.front() {
+		let offset = maze.cell_center(next) - loc;
+
+		if offset.length() > WAYPOINT_RADIUS {
+			let Vec2 { x: right, y: up } =
+				(offset / SLOWDOWN_DISTANCE).clamp(Vec2::NEG_ONE, Vec2::ONE);
+
+			*input = PlayerInput {
+				up,
+				right,
+				is_sprinting: false,
+				is_synthetic: true,
+			};
+
+			return;
+		}
+
+		autopilot.path.pop_front();
+	}
+}
+
+/// Find the shortest path from the cell `start` to the nearest tile with
+/// (uneaten) food, using a breadth-first search through the maze's open sides
+///
+/// The path includes both `start` and the cell with food. If there is no
+/// reachable food, the path is empty.
+fn path_to_food(maze: &Maze, start: IVec2) -> VecDeque<IVec2> {
+	let Some(start_pos) = maze.tile_pos(start) else {
+		return VecDeque::new();
+	};
+
+	// The cell each tile was reached from, by the tile's position, so that
+	// every tile is only visited once even if the maze wraps around
+	let mut came_from = HashMap::from([(start_pos, start)]);
+	let mut queue = VecDeque::from([start]);
+
+	while let Some(cell) = queue.pop_front() {
+		let pos = maze
+			.tile_pos(cell)
+			.expect("only cells with tiles are visited");
+		let tile = maze.get(pos.x, pos.y);
+
+		if tile.has_food() {
+			let mut path = VecDeque::from([cell]);
+			let mut pos = pos;
+
+			while pos != start_pos {
+				let cell = came_from[&pos];
+				pos = maze
+					.tile_pos(cell)
+					.expect("only cells with tiles are visited");
+				path.push_front(cell);
+			}
+
+			return path;
+		}
+
+		for dir in Direction::ALL {
+			let next = cell + dir.offset();
+
+			// Tiles on the edge of the maze can be open towards the outside
+			let Some(next_pos) = maze.tile_pos(next) else {
+				continue;
+			};
+
+			if tile.is_closed(dir) || maze.get(next_pos.x, next_pos.y).is_closed(-dir) {
+				continue;
+			}
+
+			if let Entry::Vacant(e) = came_from.entry(next_pos) {
+				e.insert(cell);
+				queue.push_back(next);
+			}
+		}
+	}
+
+	VecDeque::new()
+}
@@ -0,0 +1,186 @@
+//! The maze's configuration
+
+use std::str::FromStr;
+
+use bevy::prelude::*;
+
+use super::maze::TILE_SIZE;
+use crate::params;
+
+/// The largest allowed width or height of the maze, in tiles
+pub const MAX_MAZE_SIZE: u32 = 4096;
+
+/// The configuration of the maze
+///
+/// When the maze starts, it uses this resource if it exists, or otherwise
+/// creates it from the `maze-*` parameters (e.g. `?maze-size=64x32` or
+/// `--maze-rooms 256`), with the default value for any parameter which is
+/// missing or invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub struct MazeConfig {
+	/// Whether the maze has borders, wraps around, or is infinite (`maze-mode`)
+	pub mode: MazeMode,
+	/// The width and height of the maze, in tiles (`maze-size`, e.g. `128` or
+	/// `128x64`)
+	///
+	/// Infinite mazes have as many rooms for their area as bounded mazes of
+	/// this size
+	pub size: UVec2,
+	/// The number of rooms (open tiles with food) in the maze, in addition to
+	/// the one at the start (`maze-rooms`)
+	pub rooms: usize,
+	/// How much the tiles' textures are scaled up (`maze-tile-scale`)
+	pub tile_scale: f32,
+	/// How thick the walls are for collision, in texture pixels
+	/// (`maze-wall-thickness`)
+	pub wall_thickness: f32,
+	/// How much the food's textures are scaled (`maze-food-scale`)
+	pub food_scale: f32,
+	/// How close players need to get to the center of a tile to eat its food
+	/// (`maze-eating-distance`)
+	pub eating_distance: f32,
+}
+
+impl Default for MazeConfig {
+	fn default() -> Self {
+		Self {
+			mode: MazeMode::Bounded,
+			size: UVec2::splat(128),
+			rooms: 1024,
+			tile_scale: 5.0,
+			wall_thickness: 4.0,
+			food_scale: 1.0 / 5.0,
+			eating_distance: 32.0,
+		}
+	}
+}
+
+impl MazeConfig {
+	/// Create a `MazeConfig` from the `maze-*` parameters
+	///
+	/// Invalid parameters are reported and replaced by their default values
+	#[must_use]
+	pub fn from_params() -> Self {
+		let default = Self::default();
+
+		let config = Self {
+			mode: param("maze-mode", default.mode, str::parse),
+			size: param("maze-size", default.size, parse_size),
+			rooms: param("maze-rooms", default.rooms, |s| {
+				s.parse().map_err(|_| "not a whole number".to_string())
+			}),
+			tile_scale: param("maze-tile-scale", default.tile_scale, |s| {
+				parse_float(s).and_then(scale)
+			}),
+			wall_thickness: param("maze-wall-thickness", default.wall_thickness, |s| {
+				parse_float(s).and_then(wall_thickness)
+			}),
+			food_scale: param("maze-food-scale", default.food_scale, |s| {
+				parse_float(s).and_then(scale)
+			}),
+			eating_distance: param("maze-eating-distance", default.eating_distance, |s| {
+				parse_float(s).and_then(|distance| {
+					(distance >= 0.0)
+						.then_some(distance)
+						.ok_or_else(|| "a distance must not be negative".to_string())
+				})
+			}),
+		};
+
+		if config != default {
+			info!("using maze configuration {config:?}");
+		}
+
+		config
+	}
+}
+
+/// The shape of the maze
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MazeMode {
+	/// A maze of the configured size, surrounded by walls (`bounded`)
+	Bounded,
+	/// A maze of the configured size, which wraps around at its edges, so that
+	/// walking off one edge leads back in on the opposite one (`wrapping`)
+	Wrapping,
+	/// An endless maze, which is generated in regions as the camera approaches
+	/// them (`infinite`)
+	Infinite,
+}
+
+impl FromStr for MazeMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"bounded" => Ok(Self::Bounded),
+			"wrapping" => Ok(Self::Wrapping),
+			"infinite" => Ok(Self::Infinite),
+			_ => Err("the mode must be `bounded`, `wrapping`, or `infinite`".to_string()),
+		}
+	}
+}
+
+/// Get the parameter `name`, parsed with `parse`, or `default` if it's missing
+/// or invalid
+fn param<T>(name: &str, default: T, parse: impl FnOnce(&str) -> Result<T, String>) -> T {
+	let Some(value) = params::get(name) else {
+		return default;
+	};
+
+	parse(value.trim()).unwrap_or_else(|e| {
+		error!("invalid value '{value}' for `{name}` ({e}), using the default");
+		default
+	})
+}
+
+/// Parse a finite number
+fn parse_float(s: &str) -> Result<f32, String> {
+	let value = s.parse::<f32>().map_err(|_| "not a number".to_string())?;
+
+	if value.is_finite() {
+		Ok(value)
+	} else {
+		Err("the number must be finite".to_string())
+	}
+}
+
+/// Check that a scale is greater than zero
+fn scale(scale: f32) -> Result<f32, String> {
+	if scale > 0.0 {
+		Ok(scale)
+	} else {
+		Err("a scale must be greater than 0".to_string())
+	}
+}
+
+/// Check that walls with the given thickness leave space between them
+fn wall_thickness(thickness: f32) -> Result<f32, String> {
+	let max = TILE_SIZE.min_element() / 2.0;
+
+	if (0.0..max).contains(&thickness) {
+		Ok(thickness)
+	} else {
+		Err(format!(
+			"the wall thickness must be at least 0 and less than {max}"
+		))
+	}
+}
+
+/// Parse a maze size, either as one number for a square maze (e.g. `128`), or
+/// as the width and height (e.g. `128x64`)
+fn parse_size(s: &str) -> Result<UVec2, String> {
+	let (width, height) = s.split_once(['x', 'X', '×']).unwrap_or((s, s));
+
+	let parse_side = |side: &str| {
+		side.trim()
+			.parse::<u32>()
+			.ok()
+			.filter(|side| (1..=MAX_MAZE_SIZE).contains(side))
+			.ok_or_else(|| {
+				format!("the width and height must be whole numbers from 1 to {MAX_MAZE_SIZE}")
+			})
+	};
+
+	Ok(UVec2::new(parse_side(width)?, parse_side(height)?))
+}
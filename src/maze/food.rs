@@ -2,15 +2,19 @@ use std::fmt::{Display as FmtDisplay, Formatter, Result as FmtResult, Write};
 
 use bevy::prelude::*;
 
-use super::{maze::Tile, player::Player};
-use crate::util::{Rand, TurboRand};
+use super::{
+	maze::{Maze, TileChanged},
+	player::Player,
+};
+use crate::{
+	control::Score,
+	simulation::Position,
+	util::{Rand, TurboRand},
+};
 
 pub const FOOD_SIZE: UVec2 = UVec2::new(32, 32);
-pub const FOOD_SCALE: f32 = 1.0 / 5.0;
 pub const FOOD_AMOUNT: u32 = 49;
 
-pub const EATING_THRESHOLD: f32 = 1024.0;
-
 #[derive(Debug, Component)]
 pub struct Food;
 
@@ -21,11 +25,17 @@ impl FoodEaten {
 	pub const fn incr(&mut self) {
 		self.0 = self.0.saturating_add(1);
 	}
+
+	/// Get the number of food items eaten, not counting the one on the
+	/// starting tile
+	pub const fn count(&self) -> u16 {
+		self.0.saturating_sub(1)
+	}
 }
 
 impl FmtDisplay for FoodEaten {
 	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-		let val = self.0.saturating_sub(1);
+		let val = self.count();
 
 		if val > 99 {
 			f.write_char('+')
@@ -35,12 +45,28 @@ impl FmtDisplay for FoodEaten {
 	}
 }
 
-pub fn spawn(
-	builder: &mut ChildSpawnerCommands,
+/// Spawn a (hidden) food item with the given scale, whose sprite is set when
+/// its tile is placed
+pub fn spawn(commands: &mut Commands, scale: f32) -> Entity {
+	commands
+		.spawn((
+			Food,
+			Transform {
+				scale: Vec3::splat(scale),
+				translation: Vec3::Z,
+				..default()
+			},
+			Visibility::Hidden,
+		))
+		.id()
+}
+
+/// Get the sprite of a random food item
+pub fn sprite(
 	asset_server: &AssetServer,
 	texture_atlases: &mut Assets<TextureAtlasLayout>,
 	rng: &Rand,
-) {
+) -> Sprite {
 	let foods_handle = asset_server.load("maze/food.png");
 	let foods_atlas = TextureAtlasLayout::from_grid(FOOD_SIZE, 1, FOOD_AMOUNT, None, None);
 	let foods_atlas_handle = texture_atlases.add(foods_atlas);
@@ -51,29 +77,21 @@ pub fn spawn(
 			.expect("there should be less than usize::MAX food items"),
 	);
 
-	builder.spawn((
-		Food,
-		Sprite {
-			image: foods_handle,
-			texture_atlas: Some(TextureAtlas {
-				layout: foods_atlas_handle,
-				index,
-			}),
-			..default()
-		},
-		Transform {
-			scale: Vec3::splat(FOOD_SCALE),
-			translation: Vec3::Z,
-			..default()
-		},
-	));
+	Sprite {
+		image: foods_handle,
+		texture_atlas: Some(TextureAtlas {
+			layout: foods_atlas_handle,
+			index,
+		}),
+		..default()
+	}
 }
 
 pub fn init_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
 	let plate = asset_server.load("maze/plate.png");
 
 	commands
-		.spawn((ImageNode::new(plate), Node {
+		.spawn((StateScoped(super::STATE), ImageNode::new(plate), Node {
 			position_type: PositionType::Absolute,
 			bottom: Val::Percent(5.0),
 			right: Val::Percent(5.0),
@@ -113,48 +131,56 @@ pub fn update_ui(mut counter: Query<(&FoodEaten, &mut Text), Changed<FoodEaten>>
 	}
 }
 
+pub fn update_score(counter: Query<&FoodEaten, Changed<FoodEaten>>, mut score: ResMut<Score>) {
+	if let Ok(counter) = counter.single() {
+		score.0 = counter.count().into();
+	}
+}
+
+/// Eat the food the players are standing on
+///
+/// This runs in the `FixedUpdate` schedule, after the players have moved. The
+/// food is removed from the [`Maze`], which then also removes it from the
+/// spawned tile.
 pub fn eat(
-	mut commands: Commands,
-	player: Query<&GlobalTransform, (With<Player>, Without<Food>)>,
-	food: Query<(&GlobalTransform, &ChildOf, Entity), With<Food>>,
+	mut maze: ResMut<Maze>,
+	mut changes: EventWriter<TileChanged>,
+	players: Query<&Position, With<Player>>,
 	mut counter: Query<&mut FoodEaten>,
-	mut tiles: Query<&mut Tile>,
 ) {
-	let player = player
-		.single()
-		.expect("player entity not found")
-		.translation();
-
-	let mut current_food = None;
-	for food in &food {
-		if food.0.translation().distance_squared(player) < EATING_THRESHOLD {
-			current_food = Some((food.1, food.2));
-			break;
+	let threshold = maze.config().eating_distance.powi(2);
+
+	for player in &players {
+		let cell = maze.cell_at(player.current);
+		let Some(pos) = maze.tile_pos(cell) else {
+			continue;
+		};
+
+		// Another player may have eaten this food already this tick
+		if !maze.get(pos.x, pos.y).has_food()
+			|| maze.cell_center(cell).distance_squared(player.current) >= threshold
+		{
+			continue;
 		}
-	}
 
-	if let Some((parent, entity)) = current_food {
-		tiles
-			.get_mut(parent.parent())
-			.expect("food's tile not found")
-			.set_food(false);
+		maze.modify(pos, &mut changes, |tile| {
+			tile.set_food(false);
+		});
 
-		commands.entity(entity).despawn();
 		counter.single_mut().expect("food counter not found").incr();
 	}
 }
 
 pub fn dim(
-	player: Query<&GlobalTransform, (With<Player>, Without<Food>)>,
+	players: Query<&GlobalTransform, (With<Player>, Without<Food>)>,
 	mut food: Query<(&GlobalTransform, &mut Sprite), With<Food>>,
 ) {
-	let player = player
-		.single()
-		.expect("player entity not found")
-		.translation();
-
 	for (trans, mut sprite) in &mut food {
-		let d = trans.translation().distance_squared(player);
+		// Food is as bright as it is for the closest player
+		let d = players
+			.iter()
+			.map(|player| trans.translation().distance_squared(player.translation()))
+			.fold(f32::INFINITY, f32::min);
 
 		sprite.color.set_alpha(10000.0 / d);
 	}
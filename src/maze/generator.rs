@@ -0,0 +1,726 @@
+//! Maze generation algorithms
+//!
+//! Every algorithm generates the tiles of a maze (as a `Vec<Tile>`, with the
+//! tile at `(x, y)` at index `y * width + x`), starting from the center tile,
+//! which must be reachable from every open tile. The algorithm to use can be
+//! chosen with the `maze-generator` parameter (e.g. `?maze-generator=prim`),
+//! with the recursive backtracker being the default.
+//!
+//! Mazes can also wrap around at their edges (like a torus), in which case
+//! tiles on opposite edges are neighbors, and the algorithms carve passages
+//! across the edges like anywhere else.
+
+use bevy::prelude::*;
+
+use super::maze::{
+	Direction::{self, Bottom, Left, Right, Top},
+	Tile,
+};
+use crate::{
+	params,
+	util::{Rand, TurboRand},
+};
+
+/// All maze generation algorithms
+pub static GENERATORS: &[&dyn MazeGenerator] = &[
+	&RecursiveBacktracker,
+	&Prim,
+	&Kruskal,
+	&Wilson,
+	&Eller,
+	&Cave,
+];
+
+/// A maze generation algorithm
+pub trait MazeGenerator: Sync {
+	/// Get the name of this algorithm, used to choose it with the
+	/// `maze-generator` parameter
+	fn name(&self) -> &'static str;
+
+	/// Generate the tiles of a maze of the given size, which wraps around at
+	/// its edges if `wraps` is `true`
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile>;
+}
+
+/// Get the maze generation algorithm with the given name
+#[must_use]
+pub fn find(name: &str) -> Option<&'static dyn MazeGenerator> {
+	GENERATORS
+		.iter()
+		.find(|generator| generator.name() == name)
+		.copied()
+}
+
+/// Get the maze generation algorithm chosen with the `maze-generator`
+/// parameter
+///
+/// With `maze-generator=random`, an algorithm is picked at random. Without the
+/// parameter (or if there is no algorithm with that name), the recursive
+/// backtracker is used.
+#[must_use]
+pub fn from_params(rng: &Rand) -> &'static dyn MazeGenerator {
+	match params::get("maze-generator").as_deref() {
+		None => &RecursiveBacktracker,
+		Some("random") => *rng
+			.sample(GENERATORS)
+			.expect("there are no maze generators"),
+		Some(name) => find(name).unwrap_or_else(|| {
+			warn!("unknown maze generator '{name}', using the default");
+			&RecursiveBacktracker
+		}),
+	}
+}
+
+/// Get the index of the tile at `pos` in a maze of the given size
+fn idx(size: UVec2, UVec2 { x, y }: UVec2) -> usize {
+	usize::try_from(y * size.x + x).expect("the maze is too large")
+}
+
+/// Get the number of tiles in a maze of the given size
+fn len(size: UVec2) -> usize {
+	usize::try_from(size.element_product()).expect("the maze is too large")
+}
+
+/// Get the positions of all tiles in a maze of the given size, in index order
+fn positions(size: UVec2) -> impl Iterator<Item = UVec2> {
+	(0..size.y).flat_map(move |y| (0..size.x).map(move |x| UVec2 { x, y }))
+}
+
+/// Get the neighbors of the tile at `pos` in a maze of the given size (which
+/// wraps around at its edges if `wraps` is `true`), and the direction to each
+/// of them
+pub fn neighbors(pos: UVec2, size: UVec2, wraps: bool) -> impl Iterator<Item = (UVec2, Direction)> {
+	Direction::ALL.into_iter().filter_map(move |dir| {
+		let next = pos.as_ivec2() + dir.offset();
+
+		(wraps || (next.cmpge(IVec2::ZERO).all() && next.cmplt(size.as_ivec2()).all()))
+			.then(|| (step(size, pos, dir), dir))
+	})
+}
+
+/// Get the position of the neighbor of the tile at `pos` in the direction
+/// `dir`, wrapping around the edges of the maze
+fn step(size: UVec2, pos: UVec2, dir: Direction) -> UVec2 {
+	(pos.as_ivec2() + dir.offset())
+		.rem_euclid(size.as_ivec2())
+		.as_uvec2()
+}
+
+/// Open the passage from the tile at `pos` to its neighbor in the direction
+/// `dir` (on both tiles), returning the neighbor's position
+fn carve(maze: &mut [Tile], size: UVec2, pos: UVec2, dir: Direction) -> UVec2 {
+	let next = step(size, pos, dir);
+
+	maze[idx(size, pos)].open(dir);
+	maze[idx(size, next)].open(-dir);
+
+	next
+}
+
+/// A set of tiles, by their index, with one bit per tile
+///
+/// Unlike e.g. a `Vec` of positions, checking whether a tile is in the set
+/// takes constant time, however many tiles are in it, and the set never grows
+struct Visited(Vec<u64>);
+
+impl Visited {
+	/// Create an empty set for `len` tiles
+	fn new(len: usize) -> Self {
+		Self(vec![0; len.div_ceil(64)])
+	}
+
+	/// Whether the tile is in the set
+	fn contains(&self, tile: usize) -> bool {
+		self.0[tile / 64] & (1 << (tile % 64)) != 0
+	}
+
+	/// Add the tile to the set
+	fn insert(&mut self, tile: usize) {
+		self.0[tile / 64] |= 1 << (tile % 64);
+	}
+}
+
+/// Connect the tile at `pos` to the rest of the maze if it's fully closed (e.g.
+/// rock in a cave), by carving a passage from it towards the center until it
+/// reaches an open tile
+///
+/// The center tile must be open
+pub fn connect(maze: &mut [Tile], size: UVec2, pos: UVec2) {
+	let center = size / 2;
+	let mut pos = pos;
+	let mut is_connected = maze[idx(size, pos)] != Tile::closed();
+
+	while !is_connected {
+		let dir = if pos.x < center.x {
+			Right
+		} else if pos.x > center.x {
+			Left
+		} else if pos.y < center.y {
+			Top
+		} else {
+			Bottom
+		};
+
+		let next = (pos.as_ivec2() + dir.offset()).as_uvec2();
+		is_connected = maze[idx(size, next)] != Tile::closed();
+		pos = carve(maze, size, pos, dir);
+	}
+}
+
+/// Disjoint sets of tiles (a union-find structure), by their index
+struct Sets(Vec<usize>);
+
+impl Sets {
+	/// Create `len` sets, each containing one tile
+	fn new(len: usize) -> Self {
+		Self((0..len).collect())
+	}
+
+	/// Find the representative tile of the set containing `tile`
+	fn find(&mut self, mut tile: usize) -> usize {
+		while self.0[tile] != tile {
+			self.0[tile] = self.0[self.0[tile]];
+			tile = self.0[tile];
+		}
+
+		tile
+	}
+
+	/// Merge the sets containing `a` and `b`, returning whether they were
+	/// different sets
+	fn union(&mut self, a: usize, b: usize) -> bool {
+		let (a, b) = (self.find(a), self.find(b));
+		self.0[b] = a;
+		a != b
+	}
+}
+
+/// The recursive backtracking algorithm, which makes mazes with long, winding
+/// corridors
+///
+/// This runs in linear time, with all of its state (the visited tiles and the
+/// route back to the start) allocated up front.
+pub struct RecursiveBacktracker;
+
+/// Get the next tile in the maze for the usual recursive backtracking
+/// algorithm
+fn next_maze(
+	pos: UVec2,
+	size: UVec2,
+	wraps: bool,
+	visited: &Visited,
+	rng: &Rand,
+) -> Option<(UVec2, Direction)> {
+	let mut options = [(pos, Top); 4];
+	let mut amount = 0;
+
+	for (next, dir) in neighbors(pos, size, wraps).filter(|&(p, _)| !visited.contains(idx(size, p)))
+	{
+		options[amount] = (next, dir);
+		amount += 1;
+	}
+
+	(amount > 0).then(|| options[rng.index(..amount)])
+}
+
+impl MazeGenerator for RecursiveBacktracker {
+	fn name(&self) -> &'static str {
+		"backtracker"
+	}
+
+	#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile> {
+		let mut maze = vec![Tile::default(); len(size)];
+
+		let start = size / 2;
+		let mut visited = Visited::new(len(size));
+		visited.insert(idx(size, start));
+
+		// The route from the start to the current tile
+		let mut route = Vec::with_capacity(len(size));
+		route.push(start);
+
+		while let Some(&pos) = route.last() {
+			if let Some((next, dir)) = next_maze(pos, size, wraps, &visited, rng) {
+				carve(&mut maze, size, pos, dir);
+				visited.insert(idx(size, next));
+				route.push(next);
+			} else {
+				route.pop();
+			}
+		}
+
+		maze
+	}
+}
+
+/// Randomized Prim's algorithm, which makes mazes with many short dead ends
+pub struct Prim;
+
+impl MazeGenerator for Prim {
+	fn name(&self) -> &'static str {
+		"prim"
+	}
+
+	#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile> {
+		let mut maze = vec![Tile::default(); len(size)];
+		let mut in_maze = Visited::new(len(size));
+
+		let start = size / 2;
+		in_maze.insert(idx(size, start));
+
+		// Passages from tiles in the maze to tiles which (may) not be yet
+		let mut frontier = neighbors(start, size, wraps)
+			.map(|(next, dir)| (start, next, dir))
+			.collect::<Vec<_>>();
+
+		while !frontier.is_empty() {
+			let (pos, next, dir) = frontier.swap_remove(rng.index(..frontier.len()));
+
+			if in_maze.contains(idx(size, next)) {
+				continue;
+			}
+
+			carve(&mut maze, size, pos, dir);
+			in_maze.insert(idx(size, next));
+
+			frontier.extend(
+				neighbors(next, size, wraps)
+					.filter(|&(p, _)| !in_maze.contains(idx(size, p)))
+					.map(|(p, d)| (next, p, d)),
+			);
+		}
+
+		maze
+	}
+}
+
+/// Randomized Kruskal's algorithm, which makes mazes with many short dead ends
+pub struct Kruskal;
+
+impl MazeGenerator for Kruskal {
+	fn name(&self) -> &'static str {
+		"kruskal"
+	}
+
+	#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile> {
+		let mut maze = vec![Tile::default(); len(size)];
+		let mut sets = Sets::new(len(size));
+
+		// Every passage between two tiles, once
+		let mut passages = positions(size)
+			.flat_map(|pos| {
+				neighbors(pos, size, wraps)
+					.filter(|&(_, dir)| dir == Top || dir == Right)
+					.map(move |(_, dir)| (pos, dir))
+			})
+			.collect::<Vec<_>>();
+
+		rng.shuffle(&mut passages);
+
+		for (pos, dir) in passages {
+			let next = step(size, pos, dir);
+
+			if sets.union(idx(size, pos), idx(size, next)) {
+				carve(&mut maze, size, pos, dir);
+			}
+		}
+
+		maze
+	}
+}
+
+/// Wilson's algorithm (using loop-erased random walks), which makes uniformly
+/// random mazes
+pub struct Wilson;
+
+impl MazeGenerator for Wilson {
+	fn name(&self) -> &'static str {
+		"wilson"
+	}
+
+	#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile> {
+		let mut maze = vec![Tile::default(); len(size)];
+		let mut in_maze = Visited::new(len(size));
+		in_maze.insert(idx(size, size / 2));
+
+		// The direction in which the current walk last left each tile, so that
+		// following them from the walk's start skips any loops
+		let mut exits = vec![Top; len(size)];
+
+		let mut starts = positions(size).collect::<Vec<_>>();
+		rng.shuffle(&mut starts);
+
+		for start in starts {
+			let mut pos = start;
+
+			while !in_maze.contains(idx(size, pos)) {
+				let (next, dir) = rng
+					.sample_iter(neighbors(pos, size, wraps))
+					.expect("tiles outside of the maze have neighbors");
+
+				exits[idx(size, pos)] = dir;
+				pos = next;
+			}
+
+			let mut pos = start;
+
+			while !in_maze.contains(idx(size, pos)) {
+				in_maze.insert(idx(size, pos));
+				pos = carve(&mut maze, size, pos, exits[idx(size, pos)]);
+			}
+		}
+
+		maze
+	}
+}
+
+/// Eller's algorithm, which makes mazes one row at a time
+pub struct Eller;
+
+impl MazeGenerator for Eller {
+	fn name(&self) -> &'static str {
+		"eller"
+	}
+
+	#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile> {
+		let mut maze = vec![Tile::default(); len(size)];
+		let mut sets = Sets::new(len(size));
+
+		for y in 0..size.y {
+			let is_last_row = y + 1 == size.y;
+
+			// Join neighboring tiles in different sets, and all of them in the
+			// last row, so that every set is connected in the end
+			for x in (1..size.x).chain(wraps.then_some(0)) {
+				let prev = UVec2 {
+					x: (x + size.x - 1) % size.x,
+					y,
+				};
+				let pos = UVec2 { x, y };
+
+				if sets.find(idx(size, pos)) != sets.find(idx(size, prev))
+					&& (is_last_row || rng.bool())
+				{
+					sets.union(idx(size, pos), idx(size, prev));
+					carve(&mut maze, size, prev, Right);
+				}
+			}
+
+			if is_last_row {
+				// All tiles are connected already, but without passages across
+				// the top edge it would be a straight wall
+				if wraps {
+					for x in 0..size.x {
+						if rng.bool() {
+							carve(&mut maze, size, UVec2 { x, y }, Top);
+						}
+					}
+				}
+
+				break;
+			}
+
+			// Every set continues into the next row through at least one tile
+			let mut row = (0..size.x)
+				.map(|x| (sets.find(idx(size, UVec2 { x, y })), x))
+				.collect::<Vec<_>>();
+			row.sort_unstable();
+
+			for set in row.chunk_by(|(a, _), (b, _)| a == b) {
+				let up = rng.index(..set.len());
+
+				for (i, &(_, x)) in set.iter().enumerate() {
+					if i == up || rng.bool() {
+						let pos = UVec2 { x, y };
+						let next = carve(&mut maze, size, pos, Top);
+						sets.union(idx(size, pos), idx(size, next));
+					}
+				}
+			}
+		}
+
+		maze
+	}
+}
+
+/// A cellular automaton, which makes open, irregular caves instead of mazes
+///
+/// Tiles which are not part of the cave are fully closed
+pub struct Cave;
+
+impl Cave {
+	/// The chance of a tile starting out as rock
+	const FILL: f64 = 0.45;
+	/// The number of steps of the automaton
+	const STEPS: usize = 5;
+	/// The number of rock tiles around a tile (out of the 8 surrounding it,
+	/// counting tiles outside of a maze which doesn't wrap as rock) which make
+	/// it rock
+	const THRESHOLD: usize = 5;
+}
+
+impl MazeGenerator for Cave {
+	fn name(&self) -> &'static str {
+		"cave"
+	}
+
+	#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+	fn generate(&self, size: UVec2, wraps: bool, rng: &Rand) -> Vec<Tile> {
+		let start = size / 2;
+		let mut rock = (0..len(size))
+			.map(|_| rng.chance(Self::FILL))
+			.collect::<Vec<_>>();
+
+		for _ in 0..Self::STEPS {
+			rock = positions(size)
+				.map(|pos| {
+					let around = IRect::from_center_half_size(pos.as_ivec2(), IVec2::ONE);
+					let rocks = (around.min.y..=around.max.y)
+						.flat_map(|y| (around.min.x..=around.max.x).map(move |x| IVec2 { x, y }))
+						.filter(|&p| p != pos.as_ivec2())
+						.filter(|&p| {
+							if wraps {
+								rock[idx(size, p.rem_euclid(size.as_ivec2()).as_uvec2())]
+							} else {
+								p.cmplt(IVec2::ZERO).any()
+									|| p.cmpge(size.as_ivec2()).any()
+									|| rock[idx(size, p.as_uvec2())]
+							}
+						})
+						.count();
+
+					rocks >= Self::THRESHOLD
+				})
+				.collect();
+		}
+
+		// The start (and its surroundings) are always part of the cave
+		for (pos, _) in neighbors(start, size, wraps) {
+			rock[idx(size, pos)] = false;
+		}
+
+		rock[idx(size, start)] = false;
+
+		// Only the part of the cave connected to the start is kept, so that all
+		// of it is reachable
+		let mut maze = vec![Tile::default(); len(size)];
+		let mut in_cave = Visited::new(len(size));
+		in_cave.insert(idx(size, start));
+		let mut stack = vec![start];
+
+		while let Some(pos) = stack.pop() {
+			for (next, dir) in neighbors(pos, size, wraps) {
+				if rock[idx(size, next)] {
+					continue;
+				}
+
+				maze[idx(size, pos)].open(dir);
+
+				if !in_cave.contains(idx(size, next)) {
+					in_cave.insert(idx(size, next));
+					stack.push(next);
+				}
+			}
+		}
+
+		maze
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::{Duration, Instant};
+
+	use super::*;
+
+	/// Check that every algorithm makes valid mazes: the sides of neighboring
+	/// tiles are open towards each other or closed towards each other, every
+	/// open tile is reachable from the center, and (unless the maze wraps) the
+	/// edges of the maze are closed
+	#[test]
+	fn generators_make_valid_mazes() {
+		for generator in GENERATORS {
+			for wraps in [false, true] {
+				for size in [UVec2::new(9, 7), UVec2::splat(16), UVec2::new(3, 24)] {
+					for seed in 0..4 {
+						let maze = generator.generate(size, wraps, &Rand::with_seed(seed));
+						let context = format!(
+							"{} at {size} (wrapping: {wraps}, seed: {seed})",
+							generator.name()
+						);
+
+						assert_eq!(maze.len(), len(size), "{context}");
+						assert_valid(&maze, size, wraps, &context);
+					}
+				}
+			}
+		}
+	}
+
+	/// Check that the maze's tiles are consistent and connected (see
+	/// [`generators_make_valid_mazes`])
+	fn assert_valid(maze: &[Tile], size: UVec2, wraps: bool, context: &str) {
+		for pos in positions(size) {
+			let tile = maze[idx(size, pos)];
+
+			for (next, dir) in neighbors(pos, size, wraps) {
+				assert_eq!(
+					tile.is_open(dir),
+					maze[idx(size, next)].is_open(-dir),
+					"{context}: the {dir:?} side of {pos} doesn't match its neighbor",
+				);
+			}
+
+			if !wraps {
+				let last = size - UVec2::ONE;
+
+				for (is_edge, dir) in [
+					(pos.y == last.y, Top),
+					(pos.x == last.x, Right),
+					(pos.y == 0, Bottom),
+					(pos.x == 0, Left),
+				] {
+					assert!(
+						!is_edge || tile.is_closed(dir),
+						"{context}: {pos} is open towards the outside",
+					);
+				}
+			}
+		}
+
+		let start = size / 2;
+		assert_ne!(
+			maze[idx(size, start)],
+			Tile::closed(),
+			"{context}: the center is closed"
+		);
+
+		let mut reached = Visited::new(len(size));
+		reached.insert(idx(size, start));
+		let mut stack = vec![start];
+
+		while let Some(pos) = stack.pop() {
+			for (next, dir) in neighbors(pos, size, wraps) {
+				if maze[idx(size, pos)].is_open(dir) && !reached.contains(idx(size, next)) {
+					reached.insert(idx(size, next));
+					stack.push(next);
+				}
+			}
+		}
+
+		for pos in positions(size) {
+			assert!(
+				maze[idx(size, pos)] == Tile::closed() || reached.contains(idx(size, pos)),
+				"{context}: {pos} can't be reached from the center",
+			);
+		}
+	}
+
+	/// Measure how long each algorithm takes to generate mazes of various
+	/// sizes, and check that generation time grows (roughly) linearly with the
+	/// number of tiles
+	///
+	/// The times are printed, with the time at the larger size also as a
+	/// fraction of a frame at 60 FPS. For comparison, the quadratic recursive
+	/// backtracker which was used before is also measured, but only at the
+	/// smaller size. This is best run with a release build:
+	/// `cargo test --release -- --ignored --nocapture bench_generators`
+	#[test]
+	#[ignore = "slow benchmark"]
+	fn bench_generators() {
+		/// How many times each maze is generated
+		const RUNS: u32 = 5;
+		/// The smaller size at which the algorithms are compared
+		const SMALL: UVec2 = UVec2::splat(256);
+		/// The larger size, with 16 times as many tiles
+		const LARGE: UVec2 = UVec2::splat(1024);
+		/// How much slower than linear the algorithms may get (e.g. because of
+		/// caching), which still tells them apart from quadratic ones (which
+		/// get 16 times slower than linear)
+		const SLACK: u32 = 6;
+		/// The time of one frame at 60 FPS
+		const FRAME_BUDGET: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+		/// The recursive backtracker as it was before it used [`Visited`]
+		fn quadratic_backtracker(size: UVec2, rng: &Rand) -> Vec<Tile> {
+			let mut maze = vec![Tile::default(); len(size)];
+
+			let mut pos = size / 2;
+			let mut visited = vec![pos];
+			let mut route = vec![pos];
+
+			loop {
+				let next = rng
+					.sample_iter(neighbors(pos, size, false).filter(|(p, _)| !visited.contains(p)));
+
+				let Some((next, dir)) = next else {
+					pos = if let Some(p) = route.pop() {
+						p
+					} else {
+						break;
+					};
+					continue;
+				};
+
+				carve(&mut maze, size, pos, dir);
+
+				visited.push(next);
+				route.push(next);
+
+				pos = next;
+			}
+
+			maze
+		}
+
+		let time = |generate: &dyn Fn(UVec2, &Rand) -> Vec<Tile>, size| {
+			let start = Instant::now();
+
+			for seed in 0..RUNS {
+				generate(size, &Rand::with_seed(seed.into()));
+			}
+
+			start.elapsed() / RUNS
+		};
+
+		let quadratic = time(&quadratic_backtracker, SMALL);
+
+		println!(
+			"\n{:>21} {:>10} {:>10} {:>8}",
+			"", SMALL.x, LARGE.x, "frames"
+		);
+		println!("{:>21} {quadratic:>10.2?}", "quadratic backtracker");
+
+		for generator in GENERATORS {
+			let generate = |size, rng: &Rand| generator.generate(size, false, rng);
+			let [small, large] = [SMALL, LARGE].map(|size| time(&generate, size));
+
+			println!(
+				"{:>21} {small:>10.2?} {large:>10.2?} {:>8.2}",
+				generator.name(),
+				large.as_secs_f64() / FRAME_BUDGET.as_secs_f64(),
+			);
+
+			let ratio = (LARGE.element_product() / SMALL.element_product()) * SLACK;
+
+			assert!(
+				large <= small * ratio + Duration::from_millis(1),
+				"{} took {large:.2?} at {LARGE} but {small:.2?} at {SMALL}",
+				generator.name(),
+			);
+
+			if generator.name() == RecursiveBacktracker.name() {
+				assert!(
+					small < quadratic,
+					"the backtracker took {small:.2?} at {SMALL}, but the quadratic one took \
+					 {quadratic:.2?}",
+				);
+			}
+		}
+	}
+}
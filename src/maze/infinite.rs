@@ -0,0 +1,234 @@
+//! Infinite mazes, which are generated in regions as the camera approaches
+//! them
+//!
+//! Every region is generated on its own, deterministically from the seed and
+//! the region's position, so regions far from the camera can be evicted and
+//! generated again later. Changes to tiles (e.g. eaten food) are remembered
+//! separately, and reapplied when their region is generated again.
+
+use std::collections::HashMap;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use super::{
+	config::MazeConfig,
+	generator::{self, MazeGenerator},
+	maze::{
+		Direction::{self, Bottom, Left, Right, Top},
+		Maze, Tile, TilePos, add_rooms,
+	},
+};
+use crate::util::{Rand, TurboRand};
+
+/// The number of tiles along each side of a region
+pub const REGION_SIZE: u32 = 32;
+
+/// The size of an infinite maze in tiles
+///
+/// It's not actually infinite: its edge is about 2²⁴ tiles away from the
+/// center, and cells there still fit in an `i32`. World coordinates don't limit
+/// how far players can get, since they are kept close to the world's origin by
+/// [`recenter`](super::maze::recenter), so walking to the edge takes months
+/// (about 4 at the default tile scale, even when sprinting). The maze's center
+/// is the center of a region, so that the start is in a room.
+pub const INFINITE_SIZE: UVec2 = UVec2::splat(REGION_SIZE * ((1 << 20) + 1));
+
+/// How far (in tiles, along either axis) the player can get from the world's
+/// origin before everything is moved back towards it by whole regions
+///
+/// At the default tile scale, the `f32` world coordinates are precise to
+/// within 1/100 of a pixel this close to the origin.
+pub const RECENTER_DISTANCE: u32 = 8 * REGION_SIZE;
+
+/// The number of openings in the border between two neighboring regions
+const BORDER_OPENINGS: usize = 2;
+
+/// How far beyond the edges of the view regions are generated, in tiles
+///
+/// This is further than tiles and chunks are spawned, so that they're always
+/// spawned in generated regions
+const LOAD_MARGIN: f32 = 16.0;
+
+/// How far beyond the edges of the view regions are kept, in tiles, before
+/// they're evicted
+const KEEP_MARGIN: f32 = 64.0;
+
+/// The generated regions of an infinite maze
+pub struct Regions {
+	generator: &'static dyn MazeGenerator,
+	rng: Rand,
+	/// The number of rooms in each region
+	rooms: usize,
+	/// The tiles of the generated regions, by region position
+	loaded: HashMap<UVec2, Box<[Tile]>>,
+	/// The tiles which were changed after they were generated, by region
+	/// position and index in the region
+	changed: HashMap<UVec2, HashMap<usize, Tile>>,
+}
+
+impl Regions {
+	/// Create the regions of an infinite maze, generated with `generator`
+	#[must_use]
+	#[allow(
+		clippy::cast_precision_loss,
+		clippy::cast_possible_truncation,
+		clippy::cast_sign_loss
+	)]
+	pub fn new(generator: &'static dyn MazeGenerator, config: &MazeConfig, rng: Rand) -> Self {
+		// As many rooms for the area as in a bounded maze
+		let rooms = config.rooms as f32 * (REGION_SIZE * REGION_SIZE) as f32
+			/ config.size.element_product() as f32;
+
+		Self {
+			generator,
+			rng,
+			rooms: rooms.round() as usize,
+			loaded: HashMap::new(),
+			changed: HashMap::new(),
+		}
+	}
+
+	/// Get the position of the region with the tile at `pos`, and the tile's
+	/// index in that region
+	fn locate(pos: TilePos) -> (UVec2, usize) {
+		let pos = UVec2::new(pos.x, pos.y);
+		let local = pos % REGION_SIZE;
+		let idx = usize::try_from(local.y * REGION_SIZE + local.x).unwrap();
+
+		(pos / REGION_SIZE, idx)
+	}
+
+	/// Get the tile at `pos`, or a closed tile if its region is not generated
+	#[must_use]
+	pub fn get(&self, pos: TilePos) -> Tile {
+		let (region, idx) = Self::locate(pos);
+
+		self.loaded
+			.get(&region)
+			.map_or_else(Tile::closed, |tiles| tiles[idx])
+	}
+
+	/// Change the tile at `pos`, remembering the change for when its region is
+	/// generated again
+	pub fn set(&mut self, pos: TilePos, tile: Tile) {
+		let (region, idx) = Self::locate(pos);
+
+		if let Some(tiles) = self.loaded.get_mut(&region) {
+			tiles[idx] = tile;
+		}
+
+		self.changed.entry(region).or_default().insert(idx, tile);
+	}
+
+	/// Generate all regions with tiles in `load` (in tile positions), and
+	/// evict all regions without tiles in `keep`
+	pub fn stream(&mut self, load: IRect, keep: IRect) {
+		let regions_of = |tiles: IRect| IRect {
+			min: tiles.min.max(IVec2::ZERO) / REGION_SIZE.cast_signed(),
+			max: tiles.max.max(IVec2::ZERO) / REGION_SIZE.cast_signed(),
+		};
+
+		let keep = regions_of(keep);
+		self.loaded
+			.retain(|region, _| keep.contains(region.as_ivec2()));
+
+		let load = regions_of(load);
+		for y in load.min.y..=load.max.y {
+			for x in load.min.x..=load.max.x {
+				let region = IVec2::new(x, y).as_uvec2();
+
+				if !self.loaded.contains_key(&region) {
+					let tiles = self.generate(region);
+					self.loaded.insert(region, tiles);
+				}
+			}
+		}
+	}
+
+	/// Generate the tiles of the region at `region`, with any changes made to
+	/// them before
+	fn generate(&self, region: UVec2) -> Box<[Tile]> {
+		let size = UVec2::splat(REGION_SIZE);
+		let idx = |UVec2 { x, y }| usize::try_from(y * size.x + x).unwrap();
+		let rng = self.rng.derive(key(region));
+
+		let mut tiles = self.generator.generate(size, false, &rng);
+
+		for side in Direction::ALL {
+			for along in self.openings(region, side) {
+				let last = REGION_SIZE - 1;
+				let pos = match side {
+					Top => UVec2::new(along, last),
+					Right => UVec2::new(last, along),
+					Bottom => UVec2::new(along, 0),
+					Left => UVec2::new(0, along),
+				};
+
+				generator::connect(&mut tiles, size, pos);
+				tiles[idx(pos)].open(side);
+			}
+		}
+
+		add_rooms(&mut tiles, size, false, self.rooms, &rng);
+
+		for (&i, &tile) in self.changed.get(&region).into_iter().flatten() {
+			tiles[i] = tile;
+		}
+
+		tiles.into()
+	}
+
+	/// Get the positions along the border on the given `side` of `region` of
+	/// the openings to the neighboring region
+	///
+	/// These are the same from both sides of the border, so that neighboring
+	/// regions are connected seamlessly
+	fn openings(&self, region: UVec2, side: Direction) -> Vec<u32> {
+		// Every border is identified by the region below or left of it
+		let (region, is_vertical) = match side {
+			Top => (region, false),
+			Right => (region, true),
+			Bottom => (region.with_y(region.y.wrapping_sub(1)), false),
+			Left => (region.with_x(region.x.wrapping_sub(1)), true),
+		};
+
+		let rng = self
+			.rng
+			.substream("borders")
+			.derive(key(region))
+			.derive(is_vertical.into());
+
+		rng.sample_multiple_iter(0..REGION_SIZE, BORDER_OPENINGS)
+	}
+}
+
+/// Get the key of the region at `region` for deriving random number generators
+fn key(region: UVec2) -> u64 {
+	u64::from(region.x) << 32 | u64::from(region.y)
+}
+
+/// Generate the regions of an infinite maze which the camera approaches, and
+/// evict the ones far away from it
+#[allow(clippy::type_complexity)]
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn stream_regions(
+	mut maze: ResMut<Maze>,
+	window: Query<&Window, (With<PrimaryWindow>, Without<Camera2d>)>,
+	camera: Query<&Transform, (With<Camera2d>, Changed<Transform>)>,
+) {
+	let Ok(window) = window.single() else {
+		return;
+	};
+
+	let Ok(camera) = camera.single() else {
+		return;
+	};
+
+	let camera = camera.translation.truncate();
+	let load = maze.visible_tiles(camera, window.size(), LOAD_MARGIN);
+	let keep = maze.visible_tiles(camera, window.size(), KEEP_MARGIN);
+
+	if let Some(regions) = maze.regions_mut() {
+		regions.stream(load, keep);
+	}
+}
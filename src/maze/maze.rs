@@ -1,13 +1,17 @@
 use std::{
-	array,
+	collections::{BTreeSet, HashMap, HashSet, hash_map::Entry},
 	fmt::{Debug, Formatter, Result as FmtResult},
 	ops::Neg,
 };
 
 use bevy::{
+	asset::RenderAssetUsages,
 	prelude::*,
-	render::render_resource::{
-		Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+	render::{
+		mesh::{Indices, PrimitiveTopology},
+		render_resource::{
+			Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+		},
 	},
 	window::PrimaryWindow,
 };
@@ -15,237 +19,601 @@ use bevy_light_2d::prelude::{LightOccluder2d, LightOccluder2dShape};
 use image::{RgbaImage, imageops, load_from_memory};
 
 use self::Direction::{Bottom, Left, Right, Top};
-use crate::util::{Rand, TurboRand};
-
-pub const MAZE_SIZE: UVec2 = UVec2::splat(128);
-pub const MAZE_ROOMS: usize = 1024;
+use super::{
+	autopilot::Autopilot,
+	config::{MazeConfig, MazeMode},
+	generator::{self, MazeGenerator, neighbors},
+	infinite::{INFINITE_SIZE, RECENTER_DISTANCE, REGION_SIZE, Regions},
+	player::Player,
+};
+use crate::{
+	simulation::Position,
+	util::{Rand, TurboRand},
+};
 
 pub const TILE_SIZE: Vec2 = Vec2::new(32.0, 32.0);
-pub const TILE_SCALE: f32 = 5.0;
-pub const WALL_THICKNESS: f32 = 4.0;
 
 pub const SUBTILE_SIZE: Vec2 = Vec2::new(16.0, 16.0);
 pub const SUBTILE_SCALE: f32 = 2.0 / 5.0;
 
+/// The number of tiles along each side of a chunk
+pub const CHUNK_SIZE: u32 = 8;
+
+/// The size of a tile texture in the tile atlas, in pixels
+const ATLAS_TILE_SIZE: u32 = 5 * 16;
+/// The size of a tile texture's cell in the tile atlas, including the 1 pixel
+/// border around it (which repeats the texture's edge, so that neighboring
+/// textures don't bleed into each other)
+const ATLAS_CELL_SIZE: u32 = ATLAS_TILE_SIZE + 2;
+/// The number of tile textures along each side of the tile atlas
+const ATLAS_TILES: u32 = 16;
+
+/// The maze, which is the source of truth for the state of all tiles
+///
+/// Tiles are only spawned while they're visible, so changes to tiles must be
+/// made with [`modify`](Maze::modify), which also updates the spawned tile
+/// (via a [`TileChanged`] event)
+///
+/// The world is a grid of cells, each the size of a tile, which starts with
+/// the maze's center in the cell at the world's origin. Cells are tile
+/// positions which may be outside of the maze. A wrapping maze repeats in
+/// every direction, so that every cell has a tile, and walking off one edge of
+/// the maze leads back in on the opposite one. In an infinite maze, the
+/// world's origin follows the player (see [`recenter`]).
 #[derive(Resource)]
 pub struct Maze {
-	width: u32,
-	height: u32,
-	pub tiles: Box<[Tile]>,
-	textures: Box<[Handle<Image>; 256]>,
+	/// The configuration the maze was created with
+	config: MazeConfig,
+	/// The size of the maze in tiles
+	size: UVec2,
+	/// The cell at the world's origin
+	origin: IVec2,
+	tiles: Tiles,
+	/// The material with the tile atlas, used by all chunks
+	material: Handle<ColorMaterial>,
+}
+
+/// The storage of a maze's tiles
+enum Tiles {
+	/// All tiles of a bounded or wrapping maze, row by row
+	Bounded(Box<[Tile]>),
+	/// The generated regions of an infinite maze
+	Infinite(Regions),
 }
 
 impl Maze {
-	/// Create a new `Maze`
+	/// Create a new bounded or wrapping `Maze`, depending on the config's
+	/// `mode`
 	///
 	/// # Panic
-	/// Panics if the maze is not `width * height` tiles large
+	/// Panics if the maze is not as large as the config's `size`
 	pub fn new(
 		maze: impl Into<Box<[Tile]>>,
-		width: u32,
-		height: u32,
-		textures: Box<[Handle<Image>; 256]>,
+		config: MazeConfig,
+		material: Handle<ColorMaterial>,
 	) -> Self {
 		let tiles = maze.into();
 
 		assert_eq!(
-			width * height,
+			config.size.x * config.size.y,
 			u32::try_from(tiles.len()).unwrap(),
 			"the maze's size is incorrect"
 		);
 
 		Self {
-			width,
-			height,
-			tiles,
-			textures,
+			config,
+			size: config.size,
+			origin: (config.size / 2).as_ivec2(),
+			tiles: Tiles::Bounded(tiles),
+			material,
 		}
 	}
 
-	/// Get the index into `tiles` for `(x, y)`
-	pub fn idx(&self, x: u32, y: u32) -> usize {
-		usize::try_from(y * self.width + x).unwrap()
+	/// Create a new infinite `Maze`, with the regions around its center
+	/// already generated
+	pub fn infinite(
+		mut regions: Regions,
+		config: MazeConfig,
+		material: Handle<ColorMaterial>,
+	) -> Self {
+		let start = IRect::from_center_half_size(
+			(INFINITE_SIZE / 2).as_ivec2(),
+			IVec2::splat(REGION_SIZE.cast_signed()),
+		);
+		regions.stream(start, start);
+
+		Self {
+			config,
+			size: INFINITE_SIZE,
+			origin: (INFINITE_SIZE / 2).as_ivec2(),
+			tiles: Tiles::Infinite(regions),
+			material,
+		}
+	}
+
+	/// Get the regions of the maze, if it's infinite
+	pub const fn regions_mut(&mut self) -> Option<&mut Regions> {
+		match &mut self.tiles {
+			Tiles::Bounded(_) => None,
+			Tiles::Infinite(regions) => Some(regions),
+		}
+	}
+
+	/// Get the configuration the maze was created with
+	pub const fn config(&self) -> &MazeConfig {
+		&self.config
+	}
+
+	/// Get the index of `(x, y)` in the tiles of a bounded maze of the given
+	/// size
+	fn idx(size: UVec2, x: u32, y: u32) -> usize {
+		let [x, y, width] = [x, y, size.x].map(|n| usize::try_from(n).unwrap());
+		y * width + x
+	}
+
+	/// Get the size of the maze in tiles
+	///
+	/// Infinite mazes are [very large](INFINITE_SIZE)
+	pub const fn size(&self) -> UVec2 {
+		self.size
+	}
+
+	/// Whether the maze wraps around at its edges
+	pub const fn wraps(&self) -> bool {
+		matches!(self.config.mode, MazeMode::Wrapping)
+	}
+
+	/// Get the size of a tile in the world
+	pub fn tile_size(&self) -> Vec2 {
+		TILE_SIZE * self.config.tile_scale
+	}
+
+	/// Get the world position of the center of `cell`
+	#[allow(clippy::cast_precision_loss)]
+	pub fn cell_center(&self, cell: IVec2) -> Vec2 {
+		let offset = cell - self.origin;
+		offset.as_vec2() * self.tile_size()
+	}
+
+	/// Get the cell at the given world position
+	#[allow(clippy::cast_possible_truncation)]
+	pub fn cell_at(&self, loc: Vec2) -> IVec2 {
+		(loc / self.tile_size()).round().as_ivec2() + self.origin
+	}
+
+	/// Get the position of the tile in `cell`, if there is a tile there
+	pub fn tile_pos(&self, cell: IVec2) -> Option<TilePos> {
+		let size = self.size().as_ivec2();
+		let pos = if self.wraps() {
+			cell.rem_euclid(size)
+		} else {
+			cell
+		};
+
+		(pos.cmpge(IVec2::ZERO).all() && pos.cmplt(size).all()).then(|| TilePos {
+			x: pos.x.unsigned_abs(),
+			y: pos.y.unsigned_abs(),
+		})
+	}
+
+	/// Get the tile in `cell`, or a closed tile if there is no tile there
+	fn get_or_closed(&self, cell: IVec2) -> Tile {
+		self.tile_pos(cell)
+			.map_or_else(Tile::closed, |pos| self.get(pos.x, pos.y))
+	}
+
+	/// Get the bits selecting the texture of the tile in `cell` in the tile
+	/// atlas
+	///
+	/// The lower 4 bits are the tile's walls, and the upper 4 bits are the
+	/// corners (top left, top right, bottom left, bottom right) where walls of
+	/// the neighboring tiles meet. Tiles on the edge of a maze which doesn't
+	/// wrap have no corners.
+	fn tile_bits(&self, cell: IVec2) -> u8 {
+		let tile = self.get_or_closed(cell);
+		let mut res = tile.0 & 0b1111;
+
+		let last = self.size().as_ivec2() - IVec2::ONE;
+		let is_edge =
+			!self.wraps() && (cell.x == 0 || cell.y == 0 || cell.x == last.x || cell.y == last.y);
+
+		if !is_edge {
+			let neighbor = |dir: Direction| self.get_or_closed(cell + dir.offset());
+
+			if neighbor(Left).is_closed(Top) || neighbor(Top).is_closed(Left) {
+				res |= 0b1000_0000;
+			}
+
+			if neighbor(Right).is_closed(Top) || neighbor(Top).is_closed(Right) {
+				res |= 0b0100_0000;
+			}
+
+			if neighbor(Left).is_closed(Bottom) || neighbor(Bottom).is_closed(Left) {
+				res |= 0b0010_0000;
+			}
+
+			if neighbor(Right).is_closed(Bottom) || neighbor(Bottom).is_closed(Right) {
+				res |= 0b0001_0000;
+			}
+		}
+
+		res
+	}
+
+	/// Get the walls (as world-space rectangles) of all tiles overlapping
+	/// `area`, including the corners where walls of neighboring tiles meet
+	///
+	/// Cells without a tile are closed
+	pub fn walls(&self, area: Rect) -> Vec<Rect> {
+		let half = self.tile_size() / 2.0;
+		let inner = half - self.config.wall_thickness * self.config.tile_scale;
+
+		let min = self.cell_at(area.min);
+		let max = self.cell_at(area.max);
+
+		let mut walls = Vec::new();
+
+		for y in min.y..=max.y {
+			for x in min.x..=max.x {
+				let pos = IVec2::new(x, y);
+				let tile = self.get_or_closed(pos);
+				let center = self.cell_center(pos);
+
+				for side in Direction::ALL {
+					if tile.is_closed(side) {
+						let dir = side.offset().as_vec2();
+						// Along the wall, it covers the whole tile
+						let along = Vec2::new(dir.y, dir.x).abs() * half;
+
+						walls.push(Rect::from_corners(
+							center + dir * half + along,
+							center + dir * inner - along,
+						));
+					}
+				}
+
+				for (vertical, horizontal) in
+					[(Left, Top), (Right, Top), (Right, Bottom), (Left, Bottom)]
+				{
+					if tile.is_closed(vertical) || tile.is_closed(horizontal) {
+						// Already covered by this tile's walls
+						continue;
+					}
+
+					// Walls of the neighboring tiles meet in this corner
+					let is_closed = self
+						.get_or_closed(pos + vertical.offset())
+						.is_closed(horizontal)
+						|| self
+							.get_or_closed(pos + horizontal.offset())
+							.is_closed(vertical);
+
+					if is_closed {
+						let dir = (vertical.offset() + horizontal.offset()).as_vec2();
+						walls.push(Rect::from_corners(
+							center + dir * half,
+							center + dir * inner,
+						));
+					}
+				}
+			}
+		}
+
+		walls
 	}
 
 	/// Get the tile at `(x, y)`
 	///
+	/// In infinite mazes, tiles in regions which are not generated are closed.
+	///
 	/// # Panic
 	/// Panics if `x` is not less than the maze's width or `y` is not less than
 	/// the maze's height
 	pub fn get(&self, x: u32, y: u32) -> Tile {
-		assert!(x < self.width, "x must be less than the maze's width");
-		assert!(y < self.height, "y must be less than the maze's height");
+		assert!(x < self.size().x, "x must be less than the maze's width");
+		assert!(y < self.size().y, "y must be less than the maze's height");
 
-		self.tiles[self.idx(x, y)]
+		match &self.tiles {
+			Tiles::Bounded(tiles) => tiles[Self::idx(self.size, x, y)],
+			Tiles::Infinite(regions) => regions.get(TilePos { x, y }),
+		}
 	}
 
-	/// Spawn the tile at `(x, y)` at the given location
+	/// Modify the tile at `pos` with `f`, sending a [`TileChanged`] event if it
+	/// changed
+	///
+	/// # Panic
+	/// Panics if `pos` is outside of the maze
+	pub fn modify(
+		&mut self,
+		pos: TilePos,
+		changes: &mut EventWriter<TileChanged>,
+		f: impl FnOnce(&mut Tile),
+	) {
+		let previous = self.get(pos.x, pos.y);
+		let mut tile = previous;
+		f(&mut tile);
+
+		if tile != previous {
+			match &mut self.tiles {
+				Tiles::Bounded(tiles) => tiles[Self::idx(self.size, pos.x, pos.y)] = tile,
+				Tiles::Infinite(regions) => regions.set(pos, tile),
+			}
+
+			changes.write(TileChanged {
+				pos,
+				previous,
+				tile,
+			});
+		}
+	}
+
+	/// Get the random number generator for the food on the tile at `(x, y)`
+	///
+	/// It is derived from the tile's position, so that the same food is always
+	/// on the same tile, regardless of the order tiles are spawned in
+	fn food_rng(x: u32, y: u32, rng: &Rand) -> Rand {
+		rng.substream("food")
+			.derive(u64::from(x) << 32 | u64::from(y))
+	}
+
+	/// Get the tiles whose centers are in view of a camera at `camera` (with
+	/// a window of the given size), extended by `margin` tiles on each side
+	///
+	/// The returned rectangle (in cells, inclusive) is limited to the maze
+	/// unless it wraps, so its `min` is greater than its `max` if no tiles are
+	/// in view
+	#[allow(clippy::cast_possible_truncation)]
+	pub fn visible_tiles(&self, camera: Vec2, window: Vec2, margin: f32) -> IRect {
+		let tile_size = self.tile_size();
+		let extent = window / 2.0 + tile_size * margin;
+		let min = ((camera - extent) / tile_size).ceil().as_ivec2() + self.origin;
+		let max = ((camera + extent) / tile_size).ceil().as_ivec2() - IVec2::ONE + self.origin;
+
+		if self.wraps() {
+			return IRect { min, max };
+		}
+
+		IRect {
+			min: min.max(IVec2::ZERO),
+			max: max.min(self.size().as_ivec2() - IVec2::ONE),
+		}
+	}
+
+	/// Spawn a tile entity (with its food), which is placed at a position in
+	/// the maze with [`place_tile`](Maze::place_tile)
+	pub fn spawn_tile(&self, commands: &mut Commands) -> (Entity, TileParts) {
+		let food = super::food::spawn(commands, self.config.food_scale);
+
+		let tile = commands
+			.spawn((
+				StateScoped(super::STATE),
+				Transform::from_scale(Vec3::splat(self.config.tile_scale)),
+				Visibility::Hidden,
+			))
+			.add_child(food)
+			.id();
+
+		(tile, TileParts { food })
+	}
+
+	/// Place a tile entity (from [`spawn_tile`](Maze::spawn_tile)) in `cell`,
+	/// updating its food
+	///
+	/// The tile itself and its walls are part of its [`Chunk`]
+	///
+	/// # Panic
+	/// Panics if there is no tile in `cell`
 	#[allow(clippy::too_many_arguments)]
-	pub fn spawn_tile(
+	pub fn place_tile(
 		&self,
-		x: u32,
-		y: u32,
-		loc: Vec2,
+		entity: Entity,
+		parts: TileParts,
+		cell: IVec2,
 		commands: &mut Commands,
 		asset_server: &AssetServer,
 		texture_atlases: &mut Assets<TextureAtlasLayout>,
 		rng: &Rand,
 	) {
-		let tile = self.get(x, y);
+		let pos = self.tile_pos(cell).expect("there is no tile in the cell");
+		let tile = self.get(pos.x, pos.y);
+
+		let food_rng = Self::food_rng(pos.x, pos.y, rng);
+		commands.entity(parts.food).insert((
+			super::food::sprite(asset_server, texture_atlases, &food_rng),
+			food_visibility(tile),
+		));
+
+		let loc = self.cell_center(cell);
+		commands.entity(entity).insert((
+			tile,
+			pos,
+			parts,
+			Transform {
+				translation: loc.extend(0.0),
+				scale: Vec3::splat(self.config.tile_scale),
+				..default()
+			},
+			Visibility::Inherited,
+		));
+	}
 
-		let ti = tile_bits(self.idx(x, y), &self.tiles);
+	/// Get the cells of the chunk at `chunk` (in chunk positions), as the first
+	/// cell and the one after the last
+	///
+	/// Chunks on the edge of a maze which doesn't wrap only have the cells in
+	/// the maze
+	fn chunk_cells(&self, chunk: IVec2) -> (IVec2, IVec2) {
+		let start = chunk * CHUNK_SIZE.cast_signed();
+		let end = start + CHUNK_SIZE.cast_signed();
+
+		if self.wraps() {
+			(start, end)
+		} else {
+			(start, end.min(self.size().as_ivec2()))
+		}
+	}
 
-		commands
-			.spawn((
-				tile,
-				TilePos { x, y },
-				Sprite {
-					image: self.textures[ti as usize].clone(),
-					custom_size: Some(TILE_SIZE),
-					..default()
-				},
-				Transform {
-					translation: Vec3 {
-						x: loc.x,
-						y: loc.y,
-						..default()
-					},
-					scale: Vec3::splat(TILE_SCALE),
-					..default()
-				},
-			))
-			.with_children(|builder| {
-				let is_fully_open = tile.is_open(Top)
-					&& tile.is_open(Right)
-					&& tile.is_open(Bottom)
-					&& tile.is_open(Left);
-				let is_fully_closed = tile.is_closed(Top)
-					&& tile.is_closed(Right)
-					&& tile.is_closed(Bottom)
-					&& tile.is_closed(Left);
-
-				if !(is_fully_closed || is_fully_open) {
-					Self::spawn_tile_walls(builder, tile);
+	/// Get the world position of the chunk at `chunk` (in chunk positions),
+	/// which is the center of its first cell
+	///
+	/// The chunk's mesh and wall occluders are relative to this position
+	fn chunk_origin(&self, chunk: IVec2) -> Vec2 {
+		self.cell_center(chunk * CHUNK_SIZE.cast_signed())
+	}
+
+	/// Build the mesh of the chunk at `chunk` (in chunk positions), with a quad
+	/// for every tile, textured from the tile atlas
+	fn chunk_mesh(&self, chunk: IVec2) -> Mesh {
+		let half = self.tile_size() / 2.0;
+		let (start, end) = self.chunk_cells(chunk);
+		let first = chunk * CHUNK_SIZE.cast_signed();
+
+		let mut positions = Vec::new();
+		let mut uvs = Vec::new();
+		let mut indices = Vec::new();
+
+		for y in start.y..end.y {
+			for x in start.x..end.x {
+				let Vec2 { x: cx, y: cy } = (IVec2 { x, y } - first).as_vec2() * self.tile_size();
+				let uv = atlas_rect(self.tile_bits(IVec2 { x, y }));
+				let i = u32::try_from(positions.len()).expect("too many vertices in a chunk");
+
+				positions.extend([
+					[cx - half.x, cy + half.y, 0.0],
+					[cx + half.x, cy + half.y, 0.0],
+					[cx + half.x, cy - half.y, 0.0],
+					[cx - half.x, cy - half.y, 0.0],
+				]);
+				uvs.extend([
+					[uv.min.x, uv.min.y],
+					[uv.max.x, uv.min.y],
+					[uv.max.x, uv.max.y],
+					[uv.min.x, uv.max.y],
+				]);
+				indices.extend([i, i + 3, i + 2, i, i + 2, i + 1]);
+			}
+		}
+
+		Mesh::new(
+			PrimitiveTopology::TriangleList,
+			RenderAssetUsages::default(),
+		)
+		.with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+		.with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+		.with_inserted_indices(Indices::U32(indices))
+	}
+
+	/// Get the light occluders of the walls in the chunk at `chunk` (in chunk
+	/// positions), with straight runs of walls merged into one occluder
+	///
+	/// Tiles which are fully open or fully closed have no walls of their own
+	#[allow(clippy::cast_precision_loss)]
+	fn chunk_walls(&self, chunk: IVec2) -> Vec<(LightOccluder2d, Transform)> {
+		let (start, end) = self.chunk_cells(chunk);
+		let first = chunk * CHUNK_SIZE.cast_signed();
+
+		// The tile edges with walls, as the position of the tile above or to the
+		// right of the edge
+		let mut horizontal = BTreeSet::new();
+		let mut vertical = BTreeSet::new();
+
+		for y in start.y..end.y {
+			for x in start.x..end.x {
+				let cell = IVec2 { x, y };
+				let tile = self.get_or_closed(cell);
+
+				let is_fully_open = Direction::ALL.into_iter().all(|side| tile.is_open(side));
+				let is_fully_closed = Direction::ALL.into_iter().all(|side| tile.is_closed(side));
+
+				if is_fully_open || is_fully_closed {
+					continue;
 				}
 
-				if tile.has_food() {
-					super::food::spawn(builder, asset_server, texture_atlases, rng);
+				// Relative to the chunk, so that `f32`s are precise enough even far
+				// away from the maze's center
+				let pos = cell - first;
+
+				for side in Direction::ALL
+					.into_iter()
+					.filter(|&side| tile.is_closed(side))
+				{
+					// Keyed so that walls in the same run are next to each other
+					match side {
+						Top => horizontal.insert((pos.y + 1, pos.x)),
+						Bottom => horizontal.insert((pos.y, pos.x)),
+						Right => vertical.insert((pos.x + 1, pos.y)),
+						Left => vertical.insert((pos.x, pos.y)),
+					};
 				}
-			});
-	}
+			}
+		}
 
-	fn spawn_tile_walls(builder: &mut ChildSpawnerCommands, tile: Tile) {
-		if tile.is_closed(Top) {
-			builder.spawn((
-				LightOccluder2d {
-					shape: LightOccluder2dShape::Rectangle {
-						half_size: Vec2 {
-							x: TILE_SIZE
-								.x
-								.mul_add(TILE_SCALE, SUBTILE_SIZE.x * SUBTILE_SCALE * TILE_SCALE),
-							y: SUBTILE_SIZE.y * SUBTILE_SCALE * TILE_SCALE,
-						} / 2.0,
-					},
-				},
-				Transform {
-					translation: Vec3 {
-						x: 0.0,
-						y: TILE_SIZE.y / 2.0,
-						z: 0.0,
-					},
-					..default()
-				},
-			));
-		}
-
-		if tile.is_closed(Bottom) {
-			builder.spawn((
-				LightOccluder2d {
-					shape: LightOccluder2dShape::Rectangle {
-						half_size: Vec2 {
-							x: TILE_SIZE
-								.x
-								.mul_add(TILE_SCALE, SUBTILE_SIZE.x * SUBTILE_SCALE * TILE_SCALE),
-							y: SUBTILE_SIZE.y * SUBTILE_SCALE * TILE_SCALE,
-						} / 2.0,
-					},
-				},
-				Transform {
-					translation: Vec3 {
-						x: 0.0,
-						y: -TILE_SIZE.y / 2.0,
-						z: 0.0,
-					},
-					..default()
-				},
-			));
-		}
-
-		if tile.is_closed(Right) {
-			builder.spawn((
-				LightOccluder2d {
-					shape: LightOccluder2dShape::Rectangle {
-						half_size: Vec2 {
-							x: SUBTILE_SIZE.x * SUBTILE_SCALE * TILE_SCALE,
-							y: TILE_SIZE
-								.y
-								.mul_add(TILE_SCALE, SUBTILE_SIZE.y * SUBTILE_SCALE * TILE_SCALE),
-						} / 2.0,
-					},
-				},
-				Transform {
-					translation: Vec3 {
-						x: TILE_SIZE.x / 2.0,
-						y: 0.0,
-						z: 0.0,
-					},
-					..default()
-				},
-			));
-		}
-
-		if tile.is_closed(Left) {
-			builder.spawn((
-				LightOccluder2d {
-					shape: LightOccluder2dShape::Rectangle {
-						half_size: Vec2 {
-							x: SUBTILE_SIZE.x * SUBTILE_SCALE * TILE_SCALE,
-							y: TILE_SIZE
-								.y
-								.mul_add(TILE_SCALE, SUBTILE_SIZE.y * SUBTILE_SCALE * TILE_SCALE),
-						} / 2.0,
-					},
-				},
-				Transform {
-					translation: Vec3 {
-						x: -TILE_SIZE.x / 2.0,
-						y: 0.0,
-						z: 0.0,
+		let tile_size = self.tile_size();
+		let thickness = SUBTILE_SIZE * SUBTILE_SCALE * self.config.tile_scale;
+		let origin = -tile_size / 2.0;
+
+		let horizontal = runs(horizontal).map(|(y, x_start, x_end)| {
+			Rect::new(
+				(x_start as f32).mul_add(tile_size.x, -thickness.x / 2.0),
+				(y as f32).mul_add(tile_size.y, -thickness.y / 2.0),
+				(x_end as f32 + 1.0).mul_add(tile_size.x, thickness.x / 2.0),
+				(y as f32).mul_add(tile_size.y, thickness.y / 2.0),
+			)
+		});
+
+		let vertical = runs(vertical).map(|(x, y_start, y_end)| {
+			Rect::new(
+				(x as f32).mul_add(tile_size.x, -thickness.x / 2.0),
+				(y_start as f32).mul_add(tile_size.y, -thickness.y / 2.0),
+				(x as f32).mul_add(tile_size.x, thickness.x / 2.0),
+				(y_end as f32 + 1.0).mul_add(tile_size.y, thickness.y / 2.0),
+			)
+		});
+
+		horizontal
+			.chain(vertical)
+			.map(|rect| {
+				(
+					LightOccluder2d {
+						shape: LightOccluder2dShape::Rectangle {
+							half_size: rect.half_size(),
+						},
 					},
-					..default()
-				},
-			));
+					Transform::from_translation((origin + rect.center()).extend(0.0)),
+				)
+			})
+			.collect()
+	}
+}
+
+/// Merge sorted `(line, position)` pairs into runs of consecutive positions on
+/// the same line, as `(line, first position, last position)`
+fn runs(edges: BTreeSet<(i32, i32)>) -> impl Iterator<Item = (i32, i32, i32)> {
+	let mut runs = Vec::<(i32, i32, i32)>::new();
+
+	for (line, pos) in edges {
+		match runs.last_mut() {
+			Some((l, _, end)) if *l == line && *end + 1 == pos => *end = pos,
+			_ => runs.push((line, pos, pos)),
 		}
 	}
+
+	runs.into_iter()
 }
 
 impl Debug for Maze {
 	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
 		f.debug_struct("Maze")
-			.field("width", &self.width)
-			.field("height", &self.height)
+			.field("config", &self.config)
 			.finish_non_exhaustive()
 	}
 }
 
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-fn gen_tile_textures(
-	wall: &[&[u8]],
-	floor: &[&[u8]],
-	images: &mut Assets<Image>,
-	rng: &Rand,
-) -> [Handle<Image>; 256] {
-	let mut res = array::from_fn::<_, 256, _>(|_| None);
+fn gen_tile_atlas(wall: &[&[u8]], floor: &[&[u8]], rng: &Rand) -> Image {
+	let atlas_size = ATLAS_TILES * ATLAS_CELL_SIZE;
+	let mut atlas = RgbaImage::new(atlas_size, atlas_size);
 
 	let wall = wall
 		.iter()
@@ -277,7 +645,7 @@ fn gen_tile_textures(
 			&& tile.is_closed(Bottom)
 			&& tile.is_closed(Left);
 
-		let mut image = RgbaImage::from_raw(5 * 16, 5 * 16, vec![0; 4 * 5 * 16 * 5 * 16]).unwrap();
+		let mut image = RgbaImage::new(ATLAS_TILE_SIZE, ATLAS_TILE_SIZE);
 
 		for sy in 0..5 {
 			for sx in 0..5 {
@@ -291,31 +659,61 @@ fn gen_tile_textures(
 			}
 		}
 
-		let handle = images.add(Image {
-			data: Some(image.into_vec()),
-			texture_descriptor: TextureDescriptor {
-				label: None,
-				size: Extent3d {
-					width: 5 * 16,
-					height: 5 * 16,
-					..default()
-				},
-				dimension: TextureDimension::D2,
-				format: TextureFormat::Rgba8UnormSrgb,
-				mip_level_count: 1,
-				sample_count: 1,
-				usage: TextureUsages::TEXTURE_BINDING
-					| TextureUsages::COPY_DST
-					| TextureUsages::RENDER_ATTACHMENT,
-				view_formats: &[],
+		let cell_x = u32::from(bits) % ATLAS_TILES * ATLAS_CELL_SIZE;
+		let cell_y = u32::from(bits) / ATLAS_TILES * ATLAS_CELL_SIZE;
+		imageops::replace(&mut atlas, &image, (cell_x + 1).into(), (cell_y + 1).into());
+
+		// Repeat the texture's edge around it
+		let last = ATLAS_CELL_SIZE - 1;
+		for i in 0..ATLAS_CELL_SIZE {
+			let inner = i.clamp(1, last - 1);
+			for (x, y, from_x, from_y) in [
+				(i, 0, inner, 1),
+				(i, last, inner, last - 1),
+				(0, i, 1, inner),
+				(last, i, last - 1, inner),
+			] {
+				let pixel = *atlas.get_pixel(cell_x + from_x, cell_y + from_y);
+				atlas.put_pixel(cell_x + x, cell_y + y, pixel);
+			}
+		}
+	}
+
+	Image {
+		data: Some(atlas.into_vec()),
+		texture_descriptor: TextureDescriptor {
+			label: None,
+			size: Extent3d {
+				width: atlas_size,
+				height: atlas_size,
+				..default()
 			},
-			texture_view_descriptor: None,
-			..default()
-		});
-		res[bits as usize] = Some(handle);
+			dimension: TextureDimension::D2,
+			format: TextureFormat::Rgba8UnormSrgb,
+			mip_level_count: 1,
+			sample_count: 1,
+			usage: TextureUsages::TEXTURE_BINDING
+				| TextureUsages::COPY_DST
+				| TextureUsages::RENDER_ATTACHMENT,
+			view_formats: &[],
+		},
+		texture_view_descriptor: None,
+		..default()
 	}
+}
 
-	res.map(|o| o.expect("image creation failed"))
+/// Get the area of the tile atlas (in UV coordinates) with the texture for the
+/// given tile bits (see [`Maze::tile_bits`])
+#[allow(clippy::cast_precision_loss)]
+fn atlas_rect(bits: u8) -> Rect {
+	let atlas_size = (ATLAS_TILES * ATLAS_CELL_SIZE) as f32;
+	let cell = UVec2::new(u32::from(bits) % ATLAS_TILES, u32::from(bits) / ATLAS_TILES);
+	let min = (cell * ATLAS_CELL_SIZE + UVec2::ONE).as_vec2();
+
+	Rect::from_corners(
+		min / atlas_size,
+		(min + ATLAS_TILE_SIZE as f32) / atlas_size,
+	)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -326,6 +724,21 @@ pub enum Direction {
 	Left,
 }
 
+impl Direction {
+	/// All directions, clockwise starting from the top
+	pub const ALL: [Self; 4] = [Self::Top, Self::Right, Self::Bottom, Self::Left];
+
+	/// Get the offset to the next tile in this direction
+	pub const fn offset(self) -> IVec2 {
+		match self {
+			Self::Top => IVec2::Y,
+			Self::Right => IVec2::X,
+			Self::Bottom => IVec2::NEG_Y,
+			Self::Left => IVec2::NEG_X,
+		}
+	}
+}
+
 impl Neg for Direction {
 	type Output = Self;
 
@@ -339,15 +752,65 @@ impl Neg for Direction {
 	}
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Component)]
 pub struct TilePos {
 	pub x: u32,
 	pub y: u32,
 }
 
-#[derive(Debug, Clone, Copy, Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
 pub struct Tile(u8);
 
+/// The placed tiles' entities, by their cell
+///
+/// In wrapping mazes, the same tile can be placed in more than one cell
+#[derive(Debug, Default, Resource, Deref, DerefMut)]
+pub struct TileIndex(HashMap<IVec2, Entity>);
+
+/// Tile entities which aren't placed anywhere (and are hidden), ready to be
+/// placed again
+///
+/// Tiles are reused instead of being despawned (which is slow), so the number
+/// of tile entities never exceeds the number of tiles in view
+#[derive(Debug, Default, Resource, Deref, DerefMut)]
+pub struct TilePool(Vec<Entity>);
+
+/// A chunk of `CHUNK_SIZE`×`CHUNK_SIZE` tiles, which are rendered together as
+/// one mesh
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+pub struct Chunk(IVec2);
+
+/// The spawned chunks' entities, by their position (in chunks)
+#[derive(Debug, Default, Resource, Deref, DerefMut)]
+pub struct ChunkIndex(HashMap<IVec2, Entity>);
+
+/// The parts of a tile entity, which are updated when it's placed
+#[derive(Debug, Clone, Copy, Component)]
+pub struct TileParts {
+	/// The food, which is hidden if the tile has none
+	food: Entity,
+}
+
+/// Get the visibility of the food on `tile`
+const fn food_visibility(tile: Tile) -> Visibility {
+	if tile.has_food() {
+		Visibility::Inherited
+	} else {
+		Visibility::Hidden
+	}
+}
+
+/// A change of a tile in the [`Maze`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Event)]
+pub struct TileChanged {
+	/// The position of the tile
+	pub pos: TilePos,
+	/// The tile before the change
+	pub previous: Tile,
+	/// The tile after the change
+	pub tile: Tile,
+}
+
 impl Tile {
 	pub const fn closed() -> Self {
 		Self(0b1111)
@@ -400,27 +863,49 @@ impl Default for Tile {
 }
 
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-pub fn initialize(mut commands: Commands, rng: Res<Rand>, mut images: ResMut<Assets<Image>>) {
+pub fn initialize(
+	mut commands: Commands,
+	rng: Res<Rand>,
+	config: Option<Res<MazeConfig>>,
+	mut images: ResMut<Assets<Image>>,
+	mut materials: ResMut<Assets<ColorMaterial>>,
+) {
 	let wall = [&include_bytes!("../../assets/maze/cave-wall.png")[..]];
 	let floor = [
 		&include_bytes!("../../assets/maze/cave-floor-1.png")[..],
 		&include_bytes!("../../assets/maze/cave-floor-2.png")[..],
 	];
 
-	let textures = gen_tile_textures(&wall, &floor, &mut images, &rng);
+	let atlas = gen_tile_atlas(&wall, &floor, &rng.substream("textures"));
+	let material = materials.add(images.add(atlas));
 
-	let maze = gen_maze(&rng);
+	let maze_rng = rng.substream("maze-gen");
+	let generator = generator::from_params(&rng.substream("maze-generator"));
+	debug!(
+		"generating the maze with the {} algorithm",
+		generator.name()
+	);
+	let config = config.map_or_else(MazeConfig::from_params, |config| *config);
 
-	let maze = Maze::new(maze, MAZE_SIZE.x, MAZE_SIZE.y, Box::new(textures));
+	let maze = match config.mode {
+		MazeMode::Bounded | MazeMode::Wrapping => {
+			Maze::new(gen_maze(generator, &config, &maze_rng), config, material)
+		}
+		MazeMode::Infinite => {
+			Maze::infinite(Regions::new(generator, &config, maze_rng), config, material)
+		}
+	};
 
+	commands.insert_resource(config);
 	commands.insert_resource(maze);
+	commands.init_resource::<TileIndex>();
+	commands.init_resource::<TilePool>();
+	commands.init_resource::<ChunkIndex>();
 }
 
-#[allow(
-	clippy::cast_possible_truncation,
-	clippy::type_complexity,
-	clippy::too_many_arguments
-)]
+/// Place tiles (from the [`TilePool`], or newly spawned ones) where they came
+/// into view of the camera
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
 pub fn spawn_visible_tiles(
 	mut commands: Commands,
@@ -428,22 +913,12 @@ pub fn spawn_visible_tiles(
 	mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
 	maze: Res<Maze>,
 	rng: Res<Rand>,
-	tiles: Query<&TilePos, With<Tile>>,
+	mut index: ResMut<TileIndex>,
+	mut pool: ResMut<TilePool>,
+	parts: Query<&TileParts>,
 	window: Query<&Window, (With<PrimaryWindow>, Without<Tile>, Without<Camera2d>)>,
 	camera: Query<&Transform, (With<Camera2d>, Changed<Transform>, Without<Tile>)>,
 ) {
-	#[allow(clippy::cast_precision_loss)]
-	fn tile_position(i: u32) -> Vec2 {
-		Vec2 {
-			x: (i32::try_from(i % MAZE_SIZE.x).unwrap() - i32::try_from(MAZE_SIZE.x / 2).unwrap())
-				as f32 * TILE_SCALE
-				* TILE_SIZE.x,
-			y: (i32::try_from(i / MAZE_SIZE.x).unwrap() - i32::try_from(MAZE_SIZE.y / 2).unwrap())
-				as f32 * TILE_SCALE
-				* TILE_SIZE.y,
-		}
-	}
-
 	let Ok(window) = window.single() else {
 		return;
 	};
@@ -452,46 +927,95 @@ pub fn spawn_visible_tiles(
 		return;
 	};
 
-	let existing_tiles = tiles.iter().copied().collect::<Vec<_>>();
-
-	let new_tiles = (0..maze.tiles.len())
-		.filter(|&i| {
-			let Vec2 { x, y } = tile_position(i as u32);
-			let width = TILE_SIZE.x.mul_add(TILE_SCALE * 2.0, window.width());
-			let height = TILE_SIZE.y.mul_add(TILE_SCALE * 2.0, window.height());
-			let x_extent =
-				(camera.translation.x - width / 2.0)..(camera.translation.x + width / 2.0);
-			let y_extent =
-				(camera.translation.y - height / 2.0)..(camera.translation.y + height / 2.0);
-			x_extent.contains(&x) && y_extent.contains(&y)
-		})
-		.filter_map(|i| {
-			let pos = TilePos {
-				x: i as u32 % maze.width,
-				y: i as u32 / maze.width,
-			};
+	let visible = maze.visible_tiles(camera.translation.truncate(), window.size(), 1.0);
+
+	for y in visible.min.y..=visible.max.y {
+		for x in visible.min.x..=visible.max.x {
+			let cell = IVec2 { x, y };
+
+			if let Entry::Vacant(e) = index.entry(cell) {
+				let (entity, tile_parts) = pool
+					.pop()
+					.and_then(|entity| Some((entity, *parts.get(entity).ok()?)))
+					.unwrap_or_else(|| maze.spawn_tile(&mut commands));
+
+				maze.place_tile(
+					entity,
+					tile_parts,
+					cell,
+					&mut commands,
+					&asset_server,
+					&mut texture_atlases,
+					&rng,
+				);
+
+				e.insert(entity);
+			}
+		}
+	}
+}
 
-			(!existing_tiles.contains(&pos)).then_some((pos.x, pos.y, i))
-		});
+/// Update the placed tiles when tiles in the [`Maze`] change
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn sync_tiles(
+	mut commands: Commands,
+	maze: Res<Maze>,
+	index: Res<TileIndex>,
+	mut changes: EventReader<TileChanged>,
+	mut tiles: Query<(&mut Tile, &TileParts)>,
+) {
+	let updated = changes
+		.read()
+		.map(|change| (change.pos, change.tile))
+		.collect::<HashMap<_, _>>();
 
-	for (x, y, i) in new_tiles {
-		maze.spawn_tile(
-			x,
-			y,
-			tile_position(i as _),
-			&mut commands,
-			&asset_server,
-			&mut texture_atlases,
-			&rng,
-		);
+	if updated.is_empty() {
+		return;
+	}
+
+	// Tiles which aren't placed will be up to date when they are
+	for (&cell, &entity) in index.iter() {
+		let Some(&new_tile) = maze.tile_pos(cell).and_then(|pos| updated.get(&pos)) else {
+			continue;
+		};
+
+		let Ok((mut tile, parts)) = tiles.get_mut(entity) else {
+			continue;
+		};
+
+		*tile = new_tile;
+		commands
+			.entity(parts.food)
+			.insert(food_visibility(new_tile));
+	}
+}
+
+/// Get the chunks containing the given tiles
+///
+/// Like the tiles, the chunks' `min` is greater than their `max` if there are
+/// none
+fn chunks_of(tiles: IRect) -> IRect {
+	if tiles.min.cmpgt(tiles.max).any() {
+		return tiles;
+	}
+
+	let chunk_size = IVec2::splat(CHUNK_SIZE.cast_signed());
+
+	IRect {
+		min: tiles.min.div_euclid(chunk_size),
+		max: tiles.max.div_euclid(chunk_size),
 	}
 }
 
+/// Spawn the chunks which came into view of the camera, and despawn the ones
+/// which are no longer in view
 #[allow(clippy::type_complexity)]
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-pub fn despawn_invisible_tiles(
+pub fn stream_chunks(
 	mut commands: Commands,
-	tiles: Query<(Entity, &Transform), With<Tile>>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	maze: Res<Maze>,
+	mut index: ResMut<ChunkIndex>,
 	window: Query<&Window, (With<PrimaryWindow>, Without<Tile>, Without<Camera2d>)>,
 	camera: Query<&Transform, (With<Camera2d>, Changed<Transform>, Without<Tile>)>,
 ) {
@@ -503,141 +1027,511 @@ pub fn despawn_invisible_tiles(
 		return;
 	};
 
-	let mut old_tiles = tiles.iter().filter(|&(_, t)| {
-		let Vec3 { x, y, .. } = t.translation;
-		let width = TILE_SIZE.x.mul_add(TILE_SCALE * 3.0, window.width());
-		let height = TILE_SIZE.y.mul_add(TILE_SCALE * 3.0, window.height());
-		let x_extent = (camera.translation.x - width / 2.0)..(camera.translation.x + width / 2.0);
-		let y_extent = (camera.translation.y - height / 2.0)..(camera.translation.y + height / 2.0);
-		!x_extent.contains(&x) || !y_extent.contains(&y)
+	let camera = camera.translation.truncate();
+	let visible = chunks_of(maze.visible_tiles(camera, window.size(), 1.0));
+	let kept = chunks_of(maze.visible_tiles(camera, window.size(), 1.5));
+
+	index.retain(|pos, &mut entity| {
+		let is_kept = kept.contains(*pos);
+
+		if !is_kept {
+			// This also despawns the chunk's wall occluders
+			commands.entity(entity).despawn();
+		}
+
+		is_kept
 	});
 
-	if let Some((e, _)) = old_tiles.next() {
-		// This is very slow, so only do one per frame
-		commands.entity(e).despawn();
+	for y in visible.min.y..=visible.max.y {
+		for x in visible.min.x..=visible.max.x {
+			let pos = IVec2 { x, y };
+
+			if let Entry::Vacant(e) = index.entry(pos) {
+				let mut chunk = commands.spawn((
+					StateScoped(super::STATE),
+					Chunk(pos),
+					Mesh2d(meshes.add(maze.chunk_mesh(pos))),
+					MeshMaterial2d(maze.material.clone()),
+					Transform::from_translation(maze.chunk_origin(pos).extend(0.0)),
+				));
+
+				chunk.with_children(|builder| {
+					for wall in maze.chunk_walls(pos) {
+						builder.spawn(wall);
+					}
+				});
+
+				e.insert(chunk.id());
+			}
+		}
 	}
 }
 
-/// Get the neighbors of a tile, along with the direction towards which they are
-/// from the input tile position
-fn neighbors(UVec2 { x, y }: UVec2) -> impl Iterator<Item = (UVec2, Direction)> {
-	[
-		((x, u32::min(y + 1, MAZE_SIZE.y - 1)), Top),
-		((u32::min(x + 1, MAZE_SIZE.x - 1), y), Right),
-		((x, y.saturating_sub(1)), Bottom),
-		((x.saturating_sub(1), y), Left),
-	]
-	.into_iter()
-	.map(|(v, d)| (UVec2::from(v), d))
+/// Rebuild the meshes and wall occluders of the chunks whose tiles' walls
+/// changed
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn rebuild_chunks(
+	mut commands: Commands,
+	maze: Res<Maze>,
+	mut meshes: ResMut<Assets<Mesh>>,
+	mut changes: EventReader<TileChanged>,
+	chunks: Query<(Entity, &Chunk, &Mesh2d)>,
+) {
+	// Only the walls affect the tiles' textures
+	let modified = changes
+		.read()
+		.filter(|change| change.previous.0 & 0b1111 != change.tile.0 & 0b1111)
+		.map(|change| change.pos)
+		.collect::<HashSet<_>>();
+
+	if modified.is_empty() {
+		return;
+	}
+
+	for (entity, chunk, mesh) in &chunks {
+		// Textures also depend on the walls of the neighboring tiles, and in
+		// wrapping mazes, the same tile can be in more than one chunk
+		let (start, end) = maze.chunk_cells(chunk.0);
+		let is_dirty = (start.y - 1..=end.y).any(|y| {
+			(start.x - 1..=end.x).any(|x| {
+				maze.tile_pos(IVec2 { x, y })
+					.is_some_and(|pos| modified.contains(&pos))
+			})
+		});
+
+		if !is_dirty {
+			continue;
+		}
+
+		if let Some(mesh) = meshes.get_mut(&mesh.0) {
+			*mesh = maze.chunk_mesh(chunk.0);
+		}
+
+		// Wall runs may have been split or joined, so they're all replaced
+		commands
+			.entity(entity)
+			.despawn_related::<Children>()
+			.with_children(|builder| {
+				for wall in maze.chunk_walls(chunk.0) {
+					builder.spawn(wall);
+				}
+			});
+	}
 }
 
-/// Get the next tile in the maze for the usual recursive backtracking
-/// algorithm
-fn next_maze(pos: UVec2, visited: &[UVec2], rng: &Rand) -> Option<(UVec2, Direction)> {
-	rng.sample_iter(neighbors(pos).filter(|(p, _)| !visited.contains(p)))
+/// Return the tiles which are no longer in view of the camera to the
+/// [`TilePool`]
+#[allow(clippy::type_complexity)]
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn release_invisible_tiles(
+	mut commands: Commands,
+	maze: Res<Maze>,
+	mut index: ResMut<TileIndex>,
+	mut pool: ResMut<TilePool>,
+	window: Query<&Window, (With<PrimaryWindow>, Without<Tile>, Without<Camera2d>)>,
+	camera: Query<&Transform, (With<Camera2d>, Changed<Transform>, Without<Tile>)>,
+) {
+	let Ok(window) = window.single() else {
+		return;
+	};
+
+	let Ok(camera) = camera.single() else {
+		return;
+	};
+
+	let visible = maze.visible_tiles(camera.translation.truncate(), window.size(), 1.5);
+
+	index.retain(|&cell, &mut entity| {
+		let is_visible = visible.contains(cell);
+
+		if !is_visible {
+			commands.entity(entity).insert(Visibility::Hidden);
+			pool.push(entity);
+		}
+
+		is_visible
+	});
 }
 
+/// Move everything back towards the world's origin when the player the camera
+/// follows gets too far from it, so that world coordinates (and the `f32`s
+/// they are stored in) stay bounded
+///
+/// A wrapping maze is moved back by whole maze sizes when the player leaves its
+/// central copy, which moves the cells of the placed tiles and chunks and the
+/// autopilot's path along. An infinite maze is moved back by whole regions when
+/// the player gets [`RECENTER_DISTANCE`] tiles away from the world's origin,
+/// which moves the origin to another cell instead. Either way, this moves the
+/// players' positions, the camera, and the placed tiles and chunks, and
+/// nothing visibly changes.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-fn gen_maze(rng: &Rand) -> Vec<Tile> {
-	let us = |u32: u32| -> usize { u32.try_into().unwrap() };
-	let idx = |UVec2 { x, y }| usize::try_from(y * MAZE_SIZE.x + x).unwrap();
-
-	let mut maze = vec![Tile::default(); us(MAZE_SIZE.x) * us(MAZE_SIZE.y)];
-
-	let mut pos = MAZE_SIZE / 2;
-	let mut visited = Vec::with_capacity(us(MAZE_SIZE.x) * us(MAZE_SIZE.y));
-	visited.push(pos);
-	let mut route = vec![pos];
-
-	loop {
-		let Some((next, dir)) = next_maze(pos, &visited, rng) else {
-			pos = if let Some(p) = route.pop() {
-				p
-			} else {
-				break;
-			};
-			continue;
-		};
+pub fn recenter(
+	mut commands: Commands,
+	mut maze: ResMut<Maze>,
+	mut tile_index: ResMut<TileIndex>,
+	mut chunk_index: ResMut<ChunkIndex>,
+	autopilot: Option<ResMut<Autopilot>>,
+	mut players: Query<(&mut Position, &Player)>,
+	mut cameras: Query<&mut Transform, With<Camera2d>>,
+	mut tiles: Query<&mut Transform, (Without<Camera2d>, Without<Chunk>)>,
+	mut chunks: Query<(&mut Chunk, &mut Transform), Without<Camera2d>>,
+) {
+	let Some((position, _)) = players.iter().min_by_key(|(_, p)| p.number) else {
+		return;
+	};
 
-		maze[idx(pos)].open(dir);
-		maze[idx(next)].open(-dir);
+	let cell = maze.cell_at(position.current);
 
-		visited.push(next);
-		route.push(next);
+	let shift = match maze.config.mode {
+		MazeMode::Bounded => return,
+		MazeMode::Wrapping => {
+			let size = maze.size().as_ivec2();
+			cell.div_euclid(size) * size
+		}
+		MazeMode::Infinite => {
+			let distance = cell - maze.origin;
 
-		pos = next;
+			if distance.abs().max_element() < RECENTER_DISTANCE.cast_signed() {
+				return;
+			}
 
-		#[cfg(feature = "debug")]
-		#[allow(clippy::cast_precision_loss)]
-		if visited.len() % 512 == 0 {
-			debug!(
-				"gen_maze - {:.2}%",
-				100.0 * visited.len() as f32 / (MAZE_SIZE.x as f32 * MAZE_SIZE.y as f32)
-			);
+			// Rounded towards the origin, so that the player ends up within a
+			// region of it
+			let region = REGION_SIZE.cast_signed();
+			distance / region * region
 		}
+	};
+
+	if shift == IVec2::ZERO {
+		return;
 	}
 
+	let offset = -shift.as_vec2() * maze.tile_size();
+	debug!("recentering the maze by {shift} cells");
+
+	for (mut position, _) in &mut players {
+		position.shift(offset);
+	}
+
+	for mut camera in &mut cameras {
+		camera.translation += offset.extend(0.0);
+	}
+
+	for &entity in tile_index.values() {
+		if let Ok(mut transform) = tiles.get_mut(entity) {
+			transform.translation += offset.extend(0.0);
+		}
+	}
+
+	if !maze.wraps() {
+		// Cells stay where they are, only the world moves around them
+		maze.origin += shift;
+
+		for &entity in chunk_index.values() {
+			if let Ok((_, mut transform)) = chunks.get_mut(entity) {
+				transform.translation += offset.extend(0.0);
+			}
+		}
+
+		return;
+	}
+
+	if let Some(mut autopilot) = autopilot {
+		autopilot.shift(-shift);
+	}
+
+	**tile_index = tile_index
+		.drain()
+		.map(|(cell, entity)| (cell - shift, entity))
+		.collect();
+
+	let chunk_size = IVec2::splat(CHUNK_SIZE.cast_signed());
+
+	if shift % chunk_size == IVec2::ZERO {
+		**chunk_index = chunk_index
+			.drain()
+			.map(|(pos, entity)| {
+				let pos = pos - shift / chunk_size;
+
+				if let Ok((mut chunk, mut transform)) = chunks.get_mut(entity) {
+					chunk.0 = pos;
+					transform.translation += offset.extend(0.0);
+				}
+
+				(pos, entity)
+			})
+			.collect();
+	} else {
+		// Chunks don't line up with the copies of the maze, so they are
+		// respawned where they are now in view
+		for (_, entity) in chunk_index.drain() {
+			commands.entity(entity).despawn();
+		}
+	}
+}
+
+/// Generate the maze's tiles with `generator`, and add rooms with food to it
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+fn gen_maze(generator: &dyn MazeGenerator, config: &MazeConfig, rng: &Rand) -> Vec<Tile> {
+	let wraps = config.mode == MazeMode::Wrapping;
+	let mut maze = generator.generate(config.size, wraps, rng);
+	add_rooms(&mut maze, config.size, wraps, config.rooms, rng);
+	maze
+}
+
+/// Add `rooms` random rooms (tiles with food which are open towards all of
+/// their neighbors) to a generated maze of the given size (which wraps around
+/// at its edges if `wraps` is `true`), and one at its center
+///
+/// Rooms are only open towards tiles in the maze, so a room on the edge of a
+/// maze which doesn't wrap (or of a region of an infinite maze, whose neighbor
+/// wouldn't be open towards it) keeps its wall there. They are only added to
+/// tiles which are part of the maze, so that they aren't cut off in solid rock,
+/// which only makes a difference for caves, since every tile of the other
+/// algorithms' mazes is open towards at least one neighbor.
+pub fn add_rooms(maze: &mut [Tile], size: UVec2, wraps: bool, rooms: usize, rng: &Rand) {
+	let idx = |UVec2 { x, y }| usize::try_from(y * size.x + x).unwrap();
+
+	// Rooms are only added where the maze is, not into solid rock
+	let candidates = (0..size.x)
+		.flat_map(|x| (0..size.y).map(move |y| UVec2 { x, y }))
+		.filter(|&pos| maze[idx(pos)] != Tile::closed());
+
 	for pos in rng
-		.sample_multiple_iter(
-			(0..MAZE_SIZE.x).flat_map(|x| (0..MAZE_SIZE.y).map(move |y| UVec2 { x, y })),
-			MAZE_ROOMS,
-		)
+		.sample_multiple_iter(candidates, rooms)
 		.into_iter()
-		.chain([MAZE_SIZE / 2])
+		.chain([size / 2])
 	{
-		maze[idx(pos)]
-			.open(Direction::Top)
-			.open(Direction::Right)
-			.open(Direction::Bottom)
-			.open(Direction::Left)
-			.set_food(true);
+		maze[idx(pos)].set_food(true);
 
-		for (pos, dir) in neighbors(pos) {
-			maze[idx(pos)].open(-dir);
+		for (next, dir) in neighbors(pos, size, wraps) {
+			maze[idx(pos)].open(dir);
+			maze[idx(next)].open(-dir);
 		}
 	}
-
-	maze
 }
 
-fn tile_bits(i: usize, maze: &[Tile]) -> u8 {
-	let maze_size = (
-		usize::try_from(MAZE_SIZE.x).unwrap(),
-		usize::try_from(MAZE_SIZE.y).unwrap(),
-	);
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{control, maze::MazeGame, recording::tests::headless_app};
+
+	/// Start the maze with the given config, and teleport the primary player
+	/// and the camera by `offset` cells (as if they walked there)
+	///
+	/// Returns the app and the primary player's and the camera's positions
+	/// before teleporting
+	fn teleport(config: MazeConfig, offset: IVec2) -> (App, Vec2, Vec2) {
+		let mut app = headless_app(&MazeGame, 1);
+		app.insert_resource(config);
+
+		for _ in 0..5 {
+			app.update();
+		}
+
+		let world = app.world_mut();
+		let offset = offset.as_vec2() * world.resource::<Maze>().tile_size();
+
+		let (mut position, _) = world
+			.query::<(&mut Position, &Player)>()
+			.iter_mut(world)
+			.find(|(_, p)| p.number == 0)
+			.expect("no primary player");
+		let start = position.current;
+		*position = Position::new(start + offset);
+
+		let mut camera = world
+			.query_filtered::<&mut Transform, With<Camera2d>>()
+			.single_mut(world)
+			.unwrap();
+		let camera_start = camera.translation.truncate();
+		camera.translation += offset.extend(0.0);
+
+		for _ in 0..5 {
+			app.update();
+		}
+
+		(app, start, camera_start)
+	}
+
+	/// Get the primary player's position and the camera's translation
+	fn positions(app: &mut App) -> (Position, Vec2) {
+		let world = app.world_mut();
 
-	let tile = maze[i];
-	let tile_is_edge = !(maze_size.0..=(maze_size.1 - 1) * maze_size.0).contains(&i)
-		|| i.is_multiple_of(maze_size.0)
-		|| i % maze_size.0 == maze_size.0 - 1;
+		let (position, _) = world
+			.query::<(&Position, &Player)>()
+			.iter(world)
+			.find(|(_, p)| p.number == 0)
+			.expect("no primary player");
+		let position = *position;
 
-	let mut res = tile.0 & 0b1111;
+		let camera = world
+			.query_filtered::<&Transform, With<Camera2d>>()
+			.single(world)
+			.unwrap();
 
-	if !tile_is_edge {
-		if maze[i.saturating_sub(1)].is_closed(Top)
-			|| maze[i.saturating_add(maze_size.0)].is_closed(Left)
-		{
-			res |= 0b1000_0000;
+		(position, camera.translation.truncate())
+	}
+
+	/// Check that all placed tiles and chunks are where their cells are
+	fn assert_placed(world: &World) {
+		let maze = world.resource::<Maze>();
+
+		let tiles = world.resource::<TileIndex>();
+		assert!(!tiles.is_empty());
+
+		for (&cell, &entity) in tiles.iter() {
+			let transform = world.get::<Transform>(entity).unwrap();
+			assert!(
+				transform
+					.translation
+					.truncate()
+					.abs_diff_eq(maze.cell_center(cell), 0.01),
+				"tile in {cell} is at {}",
+				transform.translation
+			);
 		}
 
-		if maze[i.saturating_add(1)].is_closed(Top)
-			|| maze[i.saturating_add(maze_size.0)].is_closed(Right)
-		{
-			res |= 0b0100_0000;
+		let chunks = world.resource::<ChunkIndex>();
+		assert!(!chunks.is_empty());
+
+		for (&pos, &entity) in chunks.iter() {
+			let chunk = world.get::<Chunk>(entity).unwrap();
+			let transform = world.get::<Transform>(entity).unwrap();
+
+			assert_eq!(chunk.0, pos);
+			assert!(
+				transform
+					.translation
+					.truncate()
+					.abs_diff_eq(maze.chunk_origin(pos), 0.01),
+				"chunk {pos} is at {}",
+				transform.translation
+			);
 		}
+	}
+
+	/// Teleport into another copy of a wrapping maze, and check that everything
+	/// is moved back into the central copy
+	fn recenter_wrapping(size: UVec2) {
+		let _lock = control::tests::lock();
+		let config = MazeConfig {
+			mode: MazeMode::Wrapping,
+			size,
+			..default()
+		};
+		let (mut app, start, camera_start) = teleport(config, size.as_ivec2() * IVec2::new(2, -1));
+
+		let (position, camera) = positions(&mut app);
+		assert!(position.current.abs_diff_eq(start, 1.0));
+		assert!(position.previous().abs_diff_eq(start, 1.0));
+		assert!(camera.abs_diff_eq(camera_start, 1.0));
+
+		let world = app.world();
+		assert_placed(world);
 
-		if maze[i.saturating_sub(1)].is_closed(Bottom)
-			|| maze[i.saturating_sub(maze_size.0)].is_closed(Left)
-		{
-			res |= 0b0010_0000;
+		for &cell in world.resource::<TileIndex>().keys() {
+			assert!(
+				cell.cmpge(-size.as_ivec2()).all() && cell.cmplt(2 * size.as_ivec2()).all(),
+				"tile in {cell} is outside of the central copy of the maze"
+			);
 		}
+	}
+
+	/// Check that rooms don't open the edges of a maze which doesn't wrap, and
+	/// are open towards all of their neighbors, with every generator
+	#[test]
+	fn rooms_stay_inside_the_maze() {
+		let config = MazeConfig {
+			size: UVec2::new(24, 16),
+			rooms: 40,
+			..default()
+		};
+		let idx = |pos: UVec2| Maze::idx(config.size, pos.x, pos.y);
+
+		for generator in generator::GENERATORS {
+			for seed in 0..4 {
+				let maze = gen_maze(*generator, &config, &Rand::with_seed(seed));
+				let context = format!("{} (seed: {seed})", generator.name());
+				let last = config.size - UVec2::ONE;
+				let mut rooms = 0;
+
+				for y in 0..config.size.y {
+					for x in 0..config.size.x {
+						let pos = UVec2 { x, y };
+						let tile = maze[idx(pos)];
+
+						for (is_edge, dir) in [
+							(y == last.y, Top),
+							(x == last.x, Right),
+							(y == 0, Bottom),
+							(x == 0, Left),
+						] {
+							assert!(
+								!is_edge || tile.is_closed(dir),
+								"{context}: {pos} is open towards the outside",
+							);
+						}
+
+						if tile.has_food() {
+							rooms += 1;
+
+							for (next, dir) in neighbors(pos, config.size, false) {
+								assert!(
+									tile.is_open(dir) && maze[idx(next)].is_open(-dir),
+									"{context}: the room at {pos} is closed towards {next}",
+								);
+							}
+						}
+					}
+				}
 
-		if maze[i.saturating_add(1)].is_closed(Bottom)
-			|| maze[i.saturating_sub(maze_size.0)].is_closed(Right)
-		{
-			res |= 0b0001_0000;
+				assert!(rooms >= config.rooms, "{context}: only {rooms} rooms");
+			}
 		}
 	}
 
-	res
+	#[test]
+	fn recenter_aligned_with_chunks() {
+		recenter_wrapping(UVec2::splat(16));
+	}
+
+	#[test]
+	fn recenter_not_aligned_with_chunks() {
+		recenter_wrapping(UVec2::new(12, 20));
+	}
+
+	/// Teleport far away from the origin of an infinite maze, and check that
+	/// the origin follows, while the player stays in the same cell
+	#[test]
+	fn recenter_infinite() {
+		let _lock = control::tests::lock();
+		let config = MazeConfig {
+			mode: MazeMode::Infinite,
+			..default()
+		};
+		let distance = RECENTER_DISTANCE.cast_signed();
+		let offset = IVec2::new(distance + 10, -3 * distance - 7);
+		let (mut app, start, camera_start) = teleport(config, offset);
+
+		let (position, camera) = positions(&mut app);
+		let maze = app.world().resource::<Maze>();
+		let tile_size = maze.tile_size();
+		let center = (INFINITE_SIZE / 2).as_ivec2();
+		let shift = IVec2::new(distance, -3 * distance);
+
+		assert_eq!(maze.origin, center + shift);
+		assert_eq!(
+			maze.cell_at(position.current),
+			center + (start / tile_size).round().as_ivec2() + offset
+		);
+
+		let limit = maze.cell_center(maze.origin + IVec2::splat(distance));
+		assert!(position.current.abs().cmplt(limit).all());
+		assert!(position.previous().abs_diff_eq(position.current, 1.0));
+		assert!(camera.abs_diff_eq(camera_start + (offset - shift).as_vec2() * tile_size, 1.0));
+
+		assert_placed(app.world());
+	}
 }
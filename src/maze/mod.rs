@@ -1,48 +1,201 @@
 //! A randomly generated maze/cave.
 
+use std::time::Duration;
+
 use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_light_2d::light::{AmbientLight2d, Light2d};
 
-use crate::util::{PlayerInput, input};
+use crate::{
+	actions::{Action, ActionState},
+	control::{self, PlayerPosition, Score},
+	games::{Attribution, Control, GameInfo, GameState, Minigame},
+	power::IdlePolicy,
+	simulation::TickRate,
+	util::{PlayerInput, PlayerInputSet, input},
+};
 
+mod autopilot;
+mod config;
 mod food;
+mod generator;
+mod infinite;
 #[allow(clippy::module_inception)]
 mod maze;
 mod player;
 
-pub fn start(app: &mut App) {
-	app.add_systems(
-		Startup,
-		(
-			player::initialize,
-			maze::initialize,
-			camera_initialization,
-			food::init_ui,
-		),
-	);
-
-	app.add_systems(PreUpdate, input);
-
-	app.add_systems(
-		Update,
-		(
-			camera_movement,
-			player::animation,
-			player::light_flicker,
-			player::movement,
-			player::collision.after(player::movement),
-			maze::spawn_visible_tiles,
-			maze::despawn_invisible_tiles,
-			food::eat,
-			food::dim,
-			food::update_ui,
-		),
-	);
-	app.insert_resource(PlayerInput::default());
+/// The name of the maze minigame
+const NAME: &str = "maze";
+
+/// The state in which the maze minigame is running
+const STATE: GameState = GameState::Running(NAME);
+
+/// The Maze Cave minigame
+pub struct MazeGame;
+
+impl Minigame for MazeGame {
+	fn info(&self) -> &'static GameInfo {
+		&GameInfo {
+			name: NAME,
+			title: "Maze Cave",
+			description: "A randomly generated maze/cave. When nobody is playing, an autopilot \
+			              walks around looking for food.",
+			controls: &[
+				Control {
+					action: "Move",
+					inputs: "WASD, arrow keys, gamepad D-pad, gamepad left stick, or dragging \
+					         with a finger or the mouse",
+				},
+				Control {
+					action: "Sprint",
+					inputs: "Shift or gamepad left stick press",
+				},
+				Control {
+					action: "Zoom",
+					inputs: "+/- or gamepad triggers",
+				},
+				Control {
+					action: "Pause",
+					inputs: "P or gamepad start",
+				},
+			],
+			attribution: &[
+				Attribution {
+					usage: "Player character",
+					title: "Reaper",
+					author: "SamuelLee",
+					url: "https://samuellee.itch.io/reaper-animated-pixel-art",
+					files: &["maze/player-idle.png", "maze/player-walking.png"],
+				},
+				Attribution {
+					usage: "Player's torch",
+					title: "Cave Explorer",
+					author: "SamuelLee",
+					url: "https://samuellee.itch.io/cave-explorer-animated-pixel-art",
+					files: &["maze/player-idle.png", "maze/player-walking.png"],
+				},
+				Attribution {
+					usage: "Cave tiles",
+					title: "Textures",
+					author: "PiiiXL",
+					url: "https://piiixl.itch.io/textures",
+					files: &[
+						"maze/cave-floor-1.png",
+						"maze/cave-floor-2.png",
+						"maze/cave-wall.png",
+					],
+				},
+				Attribution {
+					usage: "Food",
+					title: "Pixel Food",
+					author: "ghostpixxells",
+					url: "https://ghostpixxells.itch.io/pixelfood",
+					files: &["maze/food.png", "maze/plate.png"],
+				},
+			],
+		}
+	}
+
+	fn build(&self, app: &mut App) {
+		app.add_event::<maze::TileChanged>();
+
+		app.add_systems(
+			OnEnter(STATE),
+			(
+				maze::initialize,
+				camera_initialization,
+				input_initialization,
+				food::init_ui,
+				autopilot::initialize,
+			),
+		);
+
+		app.add_systems(OnExit(STATE), cleanup);
+
+		app.add_systems(
+			PreUpdate,
+			(input, autopilot::drive)
+				.chain()
+				.in_set(PlayerInputSet)
+				.run_if(in_state(STATE).and(control::running)),
+		);
+
+		app.add_systems(
+			FixedUpdate,
+			// In a fixed order, so that replays are deterministic
+			(
+				player::movement,
+				player::collision,
+				food::eat,
+				maze::recenter,
+				food::update_score,
+				player::update_position,
+			)
+				.chain()
+				.run_if(in_state(STATE).and(control::running)),
+		);
+
+		app.add_systems(
+			Update,
+			(
+				(
+					player::join,
+					camera_movement,
+					infinite::stream_regions,
+					maze::release_invisible_tiles,
+					maze::sync_tiles,
+					maze::spawn_visible_tiles,
+					maze::stream_chunks,
+					maze::rebuild_chunks,
+				)
+					.chain(),
+				camera_zoom,
+				player::animation,
+				player::light_flicker,
+				food::dim,
+				food::update_ui,
+			)
+				.run_if(in_state(STATE).and(control::running)),
+		);
+
+		app.add_systems(Update, player::apply_quality.run_if(in_state(STATE)));
+	}
+}
+
+/// Remove the maze's resources when it stops (its entities are state-scoped)
+fn cleanup(mut commands: Commands, mut inputs: Query<&mut PlayerInput>) {
+	commands.remove_resource::<config::MazeConfig>();
+	commands.remove_resource::<maze::Maze>();
+	commands.remove_resource::<maze::TileIndex>();
+	commands.remove_resource::<maze::TilePool>();
+	commands.remove_resource::<maze::ChunkIndex>();
+	commands.remove_resource::<Score>();
+	commands.remove_resource::<PlayerPosition>();
+	commands.remove_resource::<IdlePolicy>();
+	commands.remove_resource::<TickRate>();
+	commands.remove_resource::<autopilot::Autopilot>();
+
+	// Don't leave (possibly synthetic) input behind for the next game
+	for mut input in &mut inputs {
+		*input = PlayerInput::default();
+	}
+}
+
+fn input_initialization(mut commands: Commands) {
+	commands.insert_resource(Score::default());
+	commands.insert_resource(PlayerPosition::default());
+	commands.insert_resource(IdlePolicy {
+		timeout: Duration::from_secs(30),
+		// Enough for the torch to still flicker nicely
+		update_rate: 20.0,
+		// Enough for the autopilot's walk to look smooth
+		synthetic_update_rate: 60.0,
+	});
+	commands.insert_resource(TickRate(64.0));
 }
 
 fn camera_initialization(mut commands: Commands) {
 	commands.spawn((
+		StateScoped(STATE),
 		Camera2d,
 		Light2d {
 			ambient_light: AmbientLight2d {
@@ -56,17 +209,50 @@ fn camera_initialization(mut commands: Commands) {
 	));
 }
 
+/// Zoom the camera in and out with the zoom actions
+///
+/// The camera can't zoom out further than the default, because only the tiles
+/// visible at the default zoom are spawned
+fn camera_zoom(
+	time: Res<Time>,
+	actions: Res<ActionState>,
+	mut cameras: Query<&mut Projection, With<Camera2d>>,
+) {
+	/// How fast the camera zooms, as the change of the logarithm of its scale
+	/// per second
+	const ZOOM_SPEED: f32 = 1.0;
+	/// The smallest camera scale (i.e. the furthest zoom in)
+	const MIN_SCALE: f32 = 0.5;
+
+	let zoom = actions.value(Action::ZoomIn) - actions.value(Action::ZoomOut);
+
+	if zoom == 0.0 {
+		return;
+	}
+
+	for mut projection in &mut cameras {
+		if let Projection::Orthographic(ortho) = &mut *projection {
+			let scale = ortho.scale * (-zoom * ZOOM_SPEED * time.delta_secs()).exp();
+			ortho.scale = scale.clamp(MIN_SCALE, 1.0);
+		}
+	}
+}
+
 fn camera_movement(
 	mut cameras: Query<&mut Transform, (With<Camera>, Without<player::Player>)>,
-	player: Query<&Transform, With<player::Player>>,
+	players: Query<(&Transform, &player::Player)>,
 	window: Query<&Window, With<PrimaryWindow>>,
 ) {
 	/// The free movement space on each side of the screen as a proportion of
 	/// the width/height of the screen
 	const FREE_MOVEMENT_SPACE_PROPORTION: f32 = 0.2;
 
+	// The camera follows the first active player (usually the primary player)
+	let Some((player, _)) = players.iter().min_by_key(|(_, p)| p.number) else {
+		return;
+	};
+
 	for mut camera in &mut cameras {
-		let player = player.single().expect("player entity not found");
 		let window = window.single().expect("window entity not found");
 
 		let (width, height) = (
@@ -98,3 +284,46 @@ fn camera_movement(
 		};
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::recording::{
+		Recording,
+		tests::{replay_app, run_until_exit},
+	};
+
+	/// Replay the checked-in recording of the autopilot walking around the maze
+	/// (see `recording::tests::record_maze_autopilot`)
+	#[test]
+	fn replay_autopilot() {
+		let _lock = control::tests::lock();
+		let recording =
+			Recording::from_bytes(include_bytes!("../../tests/data/maze-autopilot.wbgr"))
+				.expect("invalid recording");
+		let frames = recording.frames.len();
+		let expected = recording.outcome;
+
+		let mut app = replay_app(recording);
+
+		// The replay's outcome has to match the recorded one
+		assert_eq!(run_until_exit(&mut app, frames + 10), AppExit::Success);
+
+		let world = app.world_mut();
+		let eaten = world
+			.query::<&food::FoodEaten>()
+			.single(world)
+			.expect("food counter not found")
+			.count();
+
+		let position = world.resource::<PlayerPosition>().0;
+
+		assert_eq!(eaten, 10);
+		assert_eq!(expected.score, Some(10));
+		assert_eq!(expected.position, Some(position));
+		assert!(
+			position.abs_diff_eq(Vec2::new(-11.875, 1280.0), 0.001),
+			"the player ended up at {position}"
+		);
+	}
+}
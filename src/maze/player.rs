@@ -3,8 +3,12 @@ use std::time::Duration;
 use bevy::{color::palettes::css, prelude::*};
 use bevy_light_2d::light::PointLight2d;
 
-use super::{PlayerInput, maze, maze::Tile};
-use crate::util::{Rand, TurboRand};
+use super::maze::Maze;
+use crate::{
+	control::{PlayerPosition, Quality},
+	simulation::Position,
+	util::{InputSlot, PlayerInput, Rand, TurboRand},
+};
 
 const TILE_SIZE: UVec2 = UVec2::new(24, 32);
 const TILE_AMOUNT_IDLE: u32 = 10;
@@ -13,6 +17,7 @@ const TILE_SCALE: f32 = 2.0;
 const TILE_FRAME_TIME_SECONDS: f32 = 0.1;
 
 const MOVEMENT_SPEED: f32 = 150.0;
+const SPRINT_MULTIPLIER: f32 = 1.75;
 
 const LIGHT_INITIAL_INTENSITY: f32 = 5.0;
 const LIGHT_RADIUS: f32 = 1000.0;
@@ -20,6 +25,11 @@ const LIGHT_FALLOFF: f32 = 2.0;
 
 #[derive(Debug, Component)]
 pub struct Player {
+	/// The input slot controlling this player
+	pub slot: Entity,
+	/// The player's number (the same as its input slot's), with `0` being the
+	/// primary player
+	pub number: usize,
 	idle_atlas: Handle<TextureAtlasLayout>,
 	idle_texture: Handle<Image>,
 	walking_atlas: Handle<TextureAtlasLayout>,
@@ -32,11 +42,51 @@ pub struct Movement {
 	is_right: bool,
 }
 
+/// Spawn a player for every [`InputSlot`] which doesn't have one, and despawn
+/// players whose input slot was removed
+#[allow(clippy::too_many_arguments)]
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-pub fn initialize(
+pub fn join(
 	mut commands: Commands,
 	asset_server: Res<AssetServer>,
 	mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+	rng: Res<Rand>,
+	quality: Res<Quality>,
+	slots: Query<(Entity, &InputSlot)>,
+	players: Query<(Entity, &Player)>,
+) {
+	for (entity, player) in &players {
+		if !slots.contains(player.slot) {
+			commands.entity(entity).despawn();
+		}
+	}
+
+	for (slot, &InputSlot { player, .. }) in &slots {
+		if players.iter().any(|(_, p)| p.slot == slot) {
+			continue;
+		}
+
+		spawn(
+			&mut commands,
+			&asset_server,
+			&mut texture_atlases,
+			&rng,
+			*quality,
+			slot,
+			player,
+		);
+	}
+}
+
+/// Spawn the player with the given number, controlled by the input slot `slot`
+fn spawn(
+	commands: &mut Commands,
+	asset_server: &AssetServer,
+	texture_atlases: &mut Assets<TextureAtlasLayout>,
+	rng: &Rand,
+	quality: Quality,
+	slot: Entity,
+	number: usize,
 ) {
 	let idle_handle = asset_server.load("maze/player-idle.png");
 	let idle_atlas = TextureAtlasLayout::from_grid(TILE_SIZE, 1, TILE_AMOUNT_IDLE, None, None);
@@ -47,9 +97,20 @@ pub fn initialize(
 		TextureAtlasLayout::from_grid(TILE_SIZE, 1, TILE_AMOUNT_WALKING, None, None);
 	let walking_atlas_handle = texture_atlases.add(walking_atlas);
 
+	// Other players are tinted, so that they can be told apart
+	#[allow(clippy::cast_precision_loss)]
+	let color = if number == 0 {
+		Color::WHITE
+	} else {
+		Color::hsl(number as f32 * 137.5 % 360.0, 0.6, 0.8)
+	};
+
 	commands
 		.spawn((
+			StateScoped(super::STATE),
 			Player {
+				slot,
+				number,
 				idle_atlas: idle_atlas_handle.clone(),
 				idle_texture: idle_handle.clone(),
 				walking_atlas: walking_atlas_handle,
@@ -59,12 +120,14 @@ pub fn initialize(
 				is_right: true,
 				is_walking: false,
 			},
+			Position::new(Vec2::ZERO),
 			Sprite {
 				texture_atlas: Some(TextureAtlas {
 					layout: idle_atlas_handle,
 					..default()
 				}),
 				image: idle_handle,
+				color,
 				..default()
 			},
 			Transform {
@@ -83,7 +146,7 @@ pub fn initialize(
 		.with_children(|builder| {
 			builder.spawn((
 				PointLight2d {
-					cast_shadows: true,
+					cast_shadows: quality == Quality::High,
 					color: css::ORANGE.into(),
 					intensity: LIGHT_INITIAL_INTENSITY,
 					radius: LIGHT_RADIUS,
@@ -97,20 +160,36 @@ pub fn initialize(
 					},
 					..default()
 				},
-				FlickerTimer(Timer::new(Duration::ZERO, TimerMode::Repeating)),
+				Flicker {
+					timer: Timer::new(Duration::ZERO, TimerMode::Repeating),
+					rng: rng.substream("flicker").derive(number as u64),
+				},
 			));
 		});
 }
 
+/// Move the players based on their input
+///
+/// This runs in the `FixedUpdate` schedule
 #[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
 pub fn movement(
 	time: Res<Time>,
-	input: Res<PlayerInput>,
-	mut query: Query<(&mut Transform, &mut Movement), With<Player>>,
+	inputs: Query<&PlayerInput>,
+	mut query: Query<(&mut Position, &mut Movement, &Player)>,
 ) {
-	let distance = MOVEMENT_SPEED * time.delta_secs();
+	for (mut position, mut movement, player) in &mut query {
+		let Ok(input) = inputs.get(player.slot) else {
+			continue;
+		};
+
+		let speed = if input.is_sprinting {
+			MOVEMENT_SPEED * SPRINT_MULTIPLIER
+		} else {
+			MOVEMENT_SPEED
+		};
+
+		let distance = speed * time.delta_secs();
 
-	for (mut trans, mut movement) in &mut query {
 		if input.right > 0.0 {
 			movement.is_right = true;
 		} else if input.right < 0.0 {
@@ -119,151 +198,106 @@ pub fn movement(
 
 		movement.is_walking = input.is_moving();
 
-		trans.translation.y += distance * input.up;
-		trans.translation.x += distance * input.right;
+		position.current.y += distance * input.up;
+		position.current.x += distance * input.right;
 	}
 }
 
-#[derive(Component, Deref, DerefMut)]
-pub struct FlickerTimer(Timer);
+/// Keep the [`PlayerPosition`] up to date with the primary player's position
+pub fn update_position(players: Query<(&Position, &Player)>, mut position: ResMut<PlayerPosition>) {
+	if let Some((player, _)) = players.iter().find(|(_, p)| p.number == 0) {
+		position.0 = player.current;
+	}
+}
 
-#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-pub fn light_flicker(
-	time: Res<Time>,
-	rng: Res<Rand>,
-	mut query: Query<(&mut PointLight2d, &mut FlickerTimer)>,
-) {
-	for (mut light, mut timer) in &mut query {
-		timer.tick(time.delta());
+/// A flickering light, with its own random number stream, so that the flicker
+/// doesn't affect any other randomness
+#[derive(Component)]
+pub struct Flicker {
+	timer: Timer,
+	rng: Rand,
+}
 
-		if timer.just_finished() {
-			light.intensity = LIGHT_INITIAL_INTENSITY * ((*rng).f32() + 1.0) / 2.0;
-			timer.set_duration(Duration::from_secs_f64((*rng).f64() / 5.0));
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn light_flicker(time: Res<Time>, mut query: Query<(&mut PointLight2d, &mut Flicker)>) {
+	for (mut light, mut flicker) in &mut query {
+		flicker.timer.tick(time.delta());
+
+		if flicker.timer.just_finished() {
+			light.intensity = LIGHT_INITIAL_INTENSITY * (flicker.rng.f32() + 1.0) / 2.0;
+			let duration = Duration::from_secs_f64(flicker.rng.f64() / 5.0);
+			flicker.timer.set_duration(duration);
 		}
 	}
 }
 
-#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
-pub fn collision(
-	mut player: Query<&mut Transform, With<Player>>,
-	tiles: Query<(&Transform, &Tile), Without<Player>>,
-) {
-	use maze::Direction::{Bottom, Left, Right, Top};
-
-	let mut player = player.single_mut().expect("player entity not found");
-
-	let half_size = maze::TILE_SIZE / 2.0;
-	let inner_half = half_size - maze::WALL_THICKNESS;
-	let scaled_inner = inner_half * maze::TILE_SCALE;
-
-	#[expect(clippy::cast_precision_loss)]
-	let player_edges = [
-		player.translation.y + TILE_SIZE.y as f32 * TILE_SCALE / 2.0,
-		player.translation.x + TILE_SIZE.x as f32 * TILE_SCALE / 2.0,
-		player.translation.y - TILE_SIZE.y as f32 * TILE_SCALE / 2.0,
-		player.translation.x - TILE_SIZE.x as f32 * TILE_SCALE / 2.0,
-	];
-
-	let mut nearby_tiles = tiles
-		.iter()
-		.filter(|(t, ..)| {
-			let diff = (t.translation - player.translation).abs();
-			diff.x < 1.5 * maze::TILE_SIZE.x * maze::TILE_SCALE
-				&& diff.y < 1.5 * maze::TILE_SIZE.y * maze::TILE_SCALE
-		})
-		.collect::<Vec<_>>();
-
-	if nearby_tiles.len() < 9 {
+/// Only cast shadows from the player's light at high quality
+pub fn apply_quality(quality: Res<Quality>, mut lights: Query<&mut PointLight2d>) {
+	if !quality.is_changed() {
 		return;
 	}
 
-	#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-	nearby_tiles.sort_by_key(|(t, _)| (-t.translation.y as i32, t.translation.x as i32));
-	let nearby_tiles = nearby_tiles;
-
-	let current_tile = nearby_tiles[4];
-	let mut tile_edges = [
-		current_tile.0.translation.y + scaled_inner.y,
-		current_tile.0.translation.x + scaled_inner.x,
-		current_tile.0.translation.y - scaled_inner.y,
-		current_tile.0.translation.x - scaled_inner.x,
-	];
-
-	let mut is_above = player_edges[0] > tile_edges[0];
-	let mut is_right = player_edges[1] > tile_edges[1];
-	let mut is_below = player_edges[2] < tile_edges[2];
-	let mut is_left = player_edges[3] < tile_edges[3];
-
-	if current_tile.1.is_closed(Top) && is_above {
-		player.translation.y -= player_edges[0] - tile_edges[0];
-		tile_edges[0] = current_tile.0.translation.y + scaled_inner.y;
-		is_above = false;
-	}
-
-	if current_tile.1.is_closed(Right) && is_right {
-		player.translation.x -= player_edges[1] - tile_edges[1];
-		tile_edges[1] = current_tile.0.translation.x + scaled_inner.x;
-		is_right = false;
-	}
-
-	if current_tile.1.is_closed(Bottom) && is_below {
-		player.translation.y -= player_edges[2] - tile_edges[2];
-		tile_edges[2] = current_tile.0.translation.y - scaled_inner.y;
-		is_below = false;
+	for mut light in &mut lights {
+		light.cast_shadows = *quality == Quality::High;
 	}
+}
 
-	if current_tile.1.is_closed(Left) && is_left {
-		player.translation.x -= player_edges[3] - tile_edges[3];
-		tile_edges[3] = current_tile.0.translation.x - scaled_inner.x;
-		is_left = false;
-	}
+/// Resolve collisions of the players with the walls of the [`Maze`]
+///
+/// This runs in the `FixedUpdate` schedule, after [`movement`]. Every player
+/// is swept from its position in the previous tick to its current position,
+/// so that it can't pass through walls, however far it moved.
+#[cfg_attr(feature = "debug", tracing::instrument(skip_all))]
+pub fn collision(maze: Res<Maze>, mut players: Query<&mut Position, With<Player>>) {
+	#[expect(clippy::cast_precision_loss)]
+	let half_size = Vec2::new(TILE_SIZE.x as f32, TILE_SIZE.y as f32) * TILE_SCALE / 2.0;
 
-	let player_tile_diff = (player.translation - current_tile.0.translation).abs();
-	let coll_is_horizontal = player_tile_diff.y > player_tile_diff.x;
+	for mut position in &mut players {
+		let (from, to) = (position.previous(), position.current);
+		let area = Rect::from_corners(from.min(to) - half_size, from.max(to) + half_size);
+		let walls = maze.walls(area);
 
-	if (nearby_tiles[3].1.is_closed(Top) || nearby_tiles[1].1.is_closed(Left))
-		&& is_above
-		&& is_left
-	{
-		if coll_is_horizontal {
-			player.translation.x -= player_edges[3] - tile_edges[3];
-		} else {
-			player.translation.y -= player_edges[0] - tile_edges[0];
-		}
+		position.current = sweep(from, to, half_size, &walls);
 	}
+}
 
-	if (nearby_tiles[3].1.is_closed(Bottom) || nearby_tiles[7].1.is_closed(Left))
-		&& is_below
-		&& is_left
-	{
-		if coll_is_horizontal {
-			player.translation.x -= player_edges[3] - tile_edges[3];
-		} else {
-			player.translation.y -= player_edges[2] - tile_edges[2];
+/// Move a box with the given half size from `from` towards `to`, first
+/// horizontally, then vertically, stopping at the walls along the way
+///
+/// The box slides along walls it touches, and walls it already overlaps with
+/// at `from` are ignored.
+fn sweep(from: Vec2, to: Vec2, half_size: Vec2, walls: &[Rect]) -> Vec2 {
+	/// How far a wall may be overlapped and still be hit (e.g. because of
+	/// floating point inaccuracy)
+	const TOLERANCE: f32 = 0.01;
+
+	let mut pos = from;
+
+	for axis in [0, 1] {
+		let other = 1 - axis;
+		let mut delta = to[axis] - from[axis];
+
+		for wall in walls {
+			// The box only hits walls which it overlaps with on the other axis
+			if wall.max[other] <= pos[other] - half_size[other]
+				|| wall.min[other] >= pos[other] + half_size[other]
+			{
+				continue;
+			}
+
+			let (near, far) = (pos[axis] - half_size[axis], pos[axis] + half_size[axis]);
+
+			if delta > 0.0 && wall.min[axis] >= far - TOLERANCE {
+				delta = delta.min(wall.min[axis] - far);
+			} else if delta < 0.0 && wall.max[axis] <= near + TOLERANCE {
+				delta = delta.max(wall.max[axis] - near);
+			}
 		}
-	}
 
-	if (nearby_tiles[5].1.is_closed(Top) || nearby_tiles[1].1.is_closed(Right))
-		&& is_above
-		&& is_right
-	{
-		if coll_is_horizontal {
-			player.translation.x -= player_edges[1] - tile_edges[1];
-		} else {
-			player.translation.y -= player_edges[0] - tile_edges[0];
-		}
+		pos[axis] += delta;
 	}
 
-	if (nearby_tiles[5].1.is_closed(Bottom) || nearby_tiles[7].1.is_closed(Right))
-		&& is_below
-		&& is_right
-	{
-		if coll_is_horizontal {
-			player.translation.x -= player_edges[1] - tile_edges[1];
-		} else {
-			player.translation.y -= player_edges[2] - tile_edges[2];
-		}
-	}
+	pos
 }
 
 #[derive(Component, Deref, DerefMut)]
@@ -306,3 +340,252 @@ pub fn animation(
 		sprite.flip_x = !movement.is_right;
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use bevy::{ecs::system::ScheduleSystem, time::TimeUpdateStrategy};
+
+	use super::*;
+	use crate::{
+		maze::{
+			config::MazeConfig,
+			maze::{Direction, Tile},
+		},
+		simulation,
+	};
+
+	/// The half size of a player's collision box
+	const HALF_SIZE: Vec2 = Vec2::new(24.0, 32.0);
+
+	/// Create a maze of the given size (with the default config) from its
+	/// tiles' open sides, row by row, starting at the bottom
+	///
+	/// With the default config, tiles are 160 units wide, and their walls are
+	/// 20 units thick, so the open space in a tile reaches 60 units from its
+	/// center.
+	fn maze(size: UVec2, open: &[&[Direction]]) -> Maze {
+		let tiles = open
+			.iter()
+			.map(|sides| {
+				let mut tile = Tile::closed();
+				for &side in *sides {
+					tile.open(side);
+				}
+				tile
+			})
+			.collect::<Vec<_>>();
+
+		let config = MazeConfig { size, ..default() };
+
+		Maze::new(tiles, config, Handle::default())
+	}
+
+	/// A horizontal corridor of three tiles, with the middle one at the origin
+	fn corridor() -> Maze {
+		use Direction::{Left, Right};
+
+		maze(UVec2::new(3, 1), &[&[Right], &[Left, Right], &[Left]])
+	}
+
+	/// An L-shaped passage, going left from the tile at the origin, and down
+	/// from it, so that the walls of the other tiles meet in the corner between
+	/// the passages
+	fn bend() -> Maze {
+		use Direction::{Bottom, Left, Right, Top};
+
+		maze(UVec2::new(2, 2), &[&[], &[Top], &[Right], &[Left, Bottom]])
+	}
+
+	/// A player without textures, controlled by the input slot `slot`
+	fn player(slot: Entity, number: usize) -> Player {
+		Player {
+			slot,
+			number,
+			idle_atlas: Handle::default(),
+			idle_texture: Handle::default(),
+			walking_atlas: Handle::default(),
+			walking_texture: Handle::default(),
+		}
+	}
+
+	/// An app with the simulation, running the given `FixedUpdate` systems
+	fn app<M>(systems: impl IntoScheduleConfigs<ScheduleSystem, M>) -> App {
+		let mut app = App::new();
+
+		app.add_plugins(MinimalPlugins)
+			.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+				20,
+			)))
+			.add_systems(FixedUpdate, systems);
+		simulation::build(&mut app);
+
+		app
+	}
+
+	/// Sweep the player's box from `from` to `to` in `maze`
+	fn sweep_in(maze: &Maze, from: Vec2, to: Vec2) -> Vec2 {
+		let area = Rect::from_corners(from.min(to) - HALF_SIZE, from.max(to) + HALF_SIZE);
+		sweep(from, to, HALF_SIZE, &maze.walls(area))
+	}
+
+	#[test]
+	fn stops_at_walls() {
+		let maze = corridor();
+
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(-400.0, 0.0)),
+			Vec2::new(-196.0, 0.0)
+		);
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(0.0, 100.0)),
+			Vec2::new(0.0, 28.0)
+		);
+	}
+
+	#[test]
+	fn moves_freely_between_walls() {
+		let maze = corridor();
+
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(150.0, -20.0)),
+			Vec2::new(150.0, -20.0)
+		);
+	}
+
+	#[test]
+	fn does_not_tunnel_at_high_velocity() {
+		let maze = corridor();
+
+		// Much further than the whole maze in one step
+		for (to, expected) in [
+			(Vec2::new(1.0e5, 0.0), Vec2::new(196.0, 0.0)),
+			(Vec2::new(-1.0e5, 0.0), Vec2::new(-196.0, 0.0)),
+			(Vec2::new(0.0, 1.0e5), Vec2::new(0.0, 28.0)),
+			(Vec2::new(0.0, -1.0e5), Vec2::new(0.0, -28.0)),
+		] {
+			assert_eq!(sweep_in(&maze, Vec2::ZERO, to), expected);
+		}
+	}
+
+	#[test]
+	fn slides_along_walls() {
+		let maze = corridor();
+
+		// Blocked vertically, but still moving horizontally
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(100.0, 300.0)),
+			Vec2::new(100.0, 28.0)
+		);
+
+		// Blocked horizontally at the end of the corridor, but still moving
+		// vertically
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(-500.0, -20.0)),
+			Vec2::new(-196.0, -20.0)
+		);
+	}
+
+	#[test]
+	fn stops_at_corners() {
+		let maze = bend();
+
+		// Down the vertical passage
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(0.0, -400.0)),
+			Vec2::new(0.0, -188.0)
+		);
+
+		// Left along the horizontal passage
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(-400.0, 0.0)),
+			Vec2::new(-196.0, 0.0)
+		);
+
+		// Into the corner where the other tiles' walls meet, which is only
+		// blocked by the corner itself
+		assert_eq!(
+			sweep_in(&maze, Vec2::ZERO, Vec2::new(-50.0, -400.0)),
+			Vec2::new(-50.0, -28.0)
+		);
+	}
+
+	#[test]
+	fn ignores_overlapping_walls() {
+		let maze = corridor();
+
+		// Starting inside the wall at the top of the corridor
+		assert_eq!(
+			sweep_in(&maze, Vec2::new(0.0, 40.0), Vec2::new(0.0, 20.0)),
+			Vec2::new(0.0, 20.0)
+		);
+	}
+
+	#[test]
+	fn collision_without_tiles() {
+		/// Teleport the player far away, through the walls
+		fn teleport(mut players: Query<&mut Position, With<Player>>) {
+			for mut position in &mut players {
+				position.current += Vec2::new(1.0e5, 1.0e5);
+			}
+		}
+
+		let mut app = app((teleport, collision).chain());
+		app.insert_resource(corridor());
+
+		let player = app
+			.world_mut()
+			.spawn((player(Entity::PLACEHOLDER, 0), Position::new(Vec2::ZERO)))
+			.id();
+
+		for _ in 0..10 {
+			app.update();
+		}
+
+		let world = app.world_mut();
+
+		assert_eq!(world.query::<&Tile>().iter(world).count(), 0);
+		assert_eq!(
+			world.get::<Position>(player).unwrap().current,
+			Vec2::new(196.0, 28.0)
+		);
+	}
+
+	#[test]
+	fn sprint_per_player() {
+		let mut app = app(movement);
+		let world = app.world_mut();
+
+		let players = [false, true].map(|is_sprinting| {
+			let slot = world
+				.spawn(PlayerInput {
+					right: 1.0,
+					is_sprinting,
+					..default()
+				})
+				.id();
+
+			world
+				.spawn((
+					player(slot, usize::from(is_sprinting)),
+					Position::new(Vec2::ZERO),
+					Movement {
+						is_walking: false,
+						is_right: true,
+					},
+				))
+				.id()
+		});
+
+		for _ in 0..10 {
+			app.update();
+		}
+
+		let world = app.world();
+		let [walked, sprinted] =
+			players.map(|player| world.get::<Position>(player).unwrap().current);
+
+		assert!(walked.x > 0.0);
+		assert!((sprinted.x / walked.x - SPRINT_MULTIPLIER).abs() < 1.0e-3);
+		assert!(walked.y.abs() + sprinted.y.abs() < 1.0e-3);
+	}
+}
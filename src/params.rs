@@ -0,0 +1,161 @@
+//! Startup parameters supplied by the website or the command line
+//!
+//! On the web, parameters are read from the page's URL query string (e.g.
+//! `?game=maze`), falling back to `data-*` attributes on the `#background`
+//! canvas (e.g. `data-game="maze"`).
+//!
+//! Natively, parameters are read from command line flags (e.g. `--game maze`
+//! or `--game=maze`), falling back to `WEB_BG_*` environment variables (e.g.
+//! `WEB_BG_GAME=maze`). A flag followed by another flag (or nothing) has an
+//! empty value, so it can be used as a switch (e.g.
+//! `--replay-exit --game maze`). Arguments which are not flags or flag values
+//! are available as positional arguments.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::{env, sync::OnceLock};
+
+/// Get the value of the parameter `name`, if it was supplied
+///
+/// Parameter names are lowercase and use `-` to separate words
+#[must_use]
+pub fn get(name: &str) -> Option<String> {
+	#[cfg(target_arch = "wasm32")]
+	{
+		url_param(name).or_else(|| canvas_attribute(name))
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	{
+		flag(name).or_else(|| env_var(name))
+	}
+}
+
+/// Get the positional (non-flag) arguments, if there are any
+///
+/// This is always empty on the web
+#[must_use]
+#[cfg_attr(target_arch = "wasm32", allow(clippy::missing_const_for_fn))]
+pub fn positional() -> &'static [String] {
+	#[cfg(target_arch = "wasm32")]
+	{
+		&[]
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	{
+		&args().positional
+	}
+}
+
+/// Get a parameter from the page URL's query string
+#[cfg(target_arch = "wasm32")]
+fn url_param(name: &str) -> Option<String> {
+	let search = web_sys::window()?.location().search().ok()?;
+	web_sys::UrlSearchParams::new_with_str(&search)
+		.ok()?
+		.get(name)
+}
+
+/// Get a parameter from a `data-*` attribute of the `#background` canvas
+#[cfg(target_arch = "wasm32")]
+fn canvas_attribute(name: &str) -> Option<String> {
+	web_sys::window()?
+		.document()?
+		.get_element_by_id("background")?
+		.get_attribute(&format!("data-{name}"))
+}
+
+/// Parsed command line arguments
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Default)]
+struct Args {
+	flags: Vec<(String, String)>,
+	positional: Vec<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Args {
+	/// Parse command line arguments (without the program name)
+	fn parse(args: impl IntoIterator<Item = String>) -> Self {
+		let mut res = Self::default();
+		let mut args = args.into_iter().peekable();
+
+		while let Some(arg) = args.next() {
+			if let Some(flag) = arg.strip_prefix("--") {
+				if let Some((name, value)) = flag.split_once('=') {
+					res.flags.push((name.to_string(), value.to_string()));
+				} else {
+					let value = args.next_if(|next| !next.starts_with("--"));
+					res.flags
+						.push((flag.to_string(), value.unwrap_or_default()));
+				}
+			} else {
+				res.positional.push(arg);
+			}
+		}
+
+		res
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn args() -> &'static Args {
+	static ARGS: OnceLock<Args> = OnceLock::new();
+
+	ARGS.get_or_init(|| Args::parse(env::args().skip(1)))
+}
+
+/// Get a parameter from a command line flag
+///
+/// If a flag is given multiple times, the last value is used
+#[cfg(not(target_arch = "wasm32"))]
+fn flag(name: &str) -> Option<String> {
+	args()
+		.flags
+		.iter()
+		.rev()
+		.find(|(n, _)| n == name)
+		.map(|(_, v)| v.clone())
+}
+
+/// Get a parameter from a `WEB_BG_*` environment variable
+#[cfg(not(target_arch = "wasm32"))]
+fn env_var(name: &str) -> Option<String> {
+	env::var(format!("WEB_BG_{}", name.to_uppercase().replace('-', "_"))).ok()
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+	use super::*;
+
+	fn parse(args: &str) -> Args {
+		Args::parse(args.split_whitespace().map(String::from))
+	}
+
+	fn flags(args: &Args) -> Vec<(&str, &str)> {
+		args.flags
+			.iter()
+			.map(|(name, value)| (name.as_str(), value.as_str()))
+			.collect()
+	}
+
+	#[test]
+	fn flags_with_values() {
+		let args = parse("--game maze --seed=42 session.wbgr");
+
+		assert_eq!(flags(&args), [("game", "maze"), ("seed", "42")]);
+		assert_eq!(args.positional, ["session.wbgr"]);
+	}
+
+	#[test]
+	fn flags_without_values() {
+		let args = parse("--record --game maze --replay-exit");
+
+		assert_eq!(flags(&args), [
+			("record", ""),
+			("game", "maze"),
+			("replay-exit", "")
+		]);
+		assert!(args.positional.is_empty());
+	}
+}
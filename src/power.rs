@@ -0,0 +1,155 @@
+//! Power saving while the game is paused or nobody is playing
+//!
+//! Most visitors never interact with the background, so after some time
+//! without any (non-synthetic) [`PlayerInput`], the app drops to a low update
+//! rate, returning to full speed as soon as there is input again. How long that
+//! takes and how low the update rate drops is configured per game with an
+//! [`IdlePolicy`], which can have a separate (e.g. higher) update rate for when
+//! synthetic input (e.g. from an autopilot) is moving a player. While a gamepad
+//! is connected, the app still updates often enough to notice its input.
+
+use std::time::Duration;
+
+use bevy::{
+	prelude::*,
+	winit::{UpdateMode, WinitSettings},
+};
+
+use crate::{control::Pause, util::PlayerInput};
+
+/// How often the app updates while the game is paused
+const PAUSED_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the app updates at least while a gamepad is connected
+///
+/// Unlike keyboard and mouse input, gamepad input doesn't come from the window,
+/// so it doesn't wake up the app, and is only noticed on the next update.
+const GAMEPAD_UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A game's power saving policy
+///
+/// Games which want to save power while idle should insert this resource when
+/// they start and remove it when they stop. Without it (or with an invalid
+/// update rate), the app never becomes idle.
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub struct IdlePolicy {
+	/// How long without input until the game becomes idle
+	pub timeout: Duration,
+	/// How many times per second the app updates while idle
+	pub update_rate: f64,
+	/// How many times per second the app updates while idle, but synthetic
+	/// input is moving a player
+	pub synthetic_update_rate: f64,
+}
+
+impl IdlePolicy {
+	/// Get the time between updates while idle, or `None` if the update rate
+	/// for the current activity is not a positive, finite number
+	#[must_use]
+	pub fn interval(&self, activity: &Activity) -> Option<Duration> {
+		let rate = if activity.is_synthetic {
+			self.synthetic_update_rate
+		} else {
+			self.update_rate
+		};
+
+		(rate.is_finite() && rate > 0.0)
+			.then(|| Duration::try_from_secs_f64(rate.recip()).ok())
+			.flatten()
+	}
+}
+
+/// When there was last any player input
+#[derive(Debug, Clone, Copy, Default, Resource)]
+pub struct Activity {
+	/// The (real) time of the last input, since app startup
+	last_input: Duration,
+	/// Whether synthetic input is currently moving a player
+	is_synthetic: bool,
+}
+
+impl Activity {
+	/// Whether the game is idle according to the given policy
+	#[must_use]
+	pub fn is_idle(&self, policy: &IdlePolicy, time: &Time<Real>) -> bool {
+		time.elapsed().saturating_sub(self.last_input) >= policy.timeout
+	}
+}
+
+/// Record player input activity
+///
+/// This should run after input processing (e.g. in the `Update` schedule)
+pub fn track_activity(
+	time: Res<Time<Real>>,
+	inputs: Query<&PlayerInput>,
+	policy: Option<Res<IdlePolicy>>,
+	mut activity: ResMut<Activity>,
+) {
+	// A (new) policy starts counting from when it was inserted
+	let policy_changed = policy.is_some_and(|p| p.is_changed());
+
+	let is_playing = inputs.iter().any(|i| i.is_moving() && !i.is_synthetic);
+	let is_synthetic = inputs.iter().any(|i| i.is_moving() && i.is_synthetic);
+
+	if is_playing || policy_changed {
+		activity.last_input = time.elapsed();
+	}
+
+	if activity.is_synthetic != is_synthetic {
+		activity.is_synthetic = is_synthetic;
+	}
+}
+
+/// Update how often the app updates, based on whether the game is paused or
+/// idle, and whether a gamepad is connected
+pub fn update_mode(
+	time: Res<Time<Real>>,
+	pause: Res<Pause>,
+	policy: Option<Res<IdlePolicy>>,
+	activity: Res<Activity>,
+	gamepads: Query<(), With<Gamepad>>,
+	mut winit: ResMut<WinitSettings>,
+) {
+	if let Some(policy) = policy.as_ref().filter(|p| p.is_changed()) {
+		for rate in [policy.update_rate, policy.synthetic_update_rate] {
+			if !(rate.is_finite() && rate > 0.0) {
+				warn!("invalid idle update rate {rate}, the game won't become idle at that rate");
+			}
+		}
+	}
+
+	// Gamepads have to be polled to notice their input in time (e.g. to resume
+	// the game with the pause action)
+	let max_interval = if gamepads.is_empty() {
+		Duration::MAX
+	} else {
+		GAMEPAD_UPDATE_INTERVAL
+	};
+
+	let settings = if pause.is_paused() {
+		let interval = PAUSED_UPDATE_INTERVAL.min(max_interval);
+
+		WinitSettings {
+			focused_mode: UpdateMode::reactive_low_power(interval),
+			unfocused_mode: UpdateMode::reactive_low_power(interval),
+		}
+	} else if let Some(interval) = policy
+		.filter(|p| activity.is_idle(p, &time))
+		.and_then(|p| p.interval(&activity))
+		.map(|interval| interval.min(max_interval))
+	{
+		WinitSettings {
+			focused_mode: UpdateMode::reactive(interval),
+			unfocused_mode: UpdateMode::reactive_low_power(interval),
+		}
+	} else {
+		WinitSettings::default()
+	};
+
+	if settings.focused_mode != winit.focused_mode
+		|| settings.unfocused_mode != winit.unfocused_mode
+	{
+		debug!("switching to update mode {settings:?}");
+		*winit = settings;
+	}
+}
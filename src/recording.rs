@@ -0,0 +1,1041 @@
+//! Recording sessions and replaying them deterministically
+//!
+//! A [`Recording`] contains the seed, the game, and every frame's (virtual)
+//! delta time, [`PlayerInput`]s, and [`Action`] values, along with the outcome
+//! of the session (the score and the primary player's position at its end).
+//! Replaying a recording restarts its game with its seed, then feeds the
+//! recorded delta times, inputs, and actions into the app instead of the real
+//! ones, so that the game plays out in exactly the same way, as long as the
+//! window has the same size. At the end of a replay, its outcome is compared to
+//! the recorded one.
+//!
+//! Recordings are started and stopped with the
+//! [`Controller`](crate::control::Controller), or natively with the `record`
+//! parameter (e.g. `--record session.wbgr`), which saves the recording to that
+//! file when the app exits. They are replayed with the `Controller`, or
+//! natively with the `replay` parameter (e.g. `--replay session.wbgr`). With
+//! the `replay-exit` parameter, the app exits after the replay, with an error
+//! if the outcome didn't match, so that recordings can be used as regression
+//! tests.
+
+use std::{sync::Mutex, time::Duration};
+
+use bevy::{app::RunFixedMainLoopSystem, prelude::*, time::TimeUpdateStrategy};
+
+use crate::{
+	actions::{Action, ActionState},
+	control::{self, ControlCommand, Pause, PlayerPosition, Score},
+	events,
+	games::{self, GameState, SwitchGame},
+	params,
+	util::{InputSlot, InputSource, PlayerInput, Rand},
+};
+
+/// The last finished recording, serialized
+static LAST_RECORDING: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+/// The first bytes of every serialized recording
+const MAGIC: &[u8; 4] = b"WBGR";
+
+/// The version of the serialization format
+const VERSION: u8 = 2;
+
+/// The frame input or action count which means that a frame's inputs or actions
+/// are the same as the previous frame's
+const SAME: u8 = u8::MAX;
+
+/// A recorded session
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+	/// The seed used for all random number generation
+	pub seed: u64,
+	/// The name of the game that was played
+	pub game: String,
+	/// The frames of the session, in order
+	pub frames: Vec<Frame>,
+	/// The outcome of the session
+	pub outcome: Outcome,
+}
+
+/// A recorded frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+	/// The (virtual) time since the last frame
+	pub delta: Duration,
+	/// The input of every player, by player number
+	pub inputs: Vec<(u8, PlayerInput)>,
+	/// How far every pressed action (except [`Pause`](Action::Pause)) is
+	/// pressed
+	pub actions: Vec<(Action, f32)>,
+}
+
+/// The outcome of a session
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Outcome {
+	/// The score at the end of the session, if the game has one
+	pub score: Option<u32>,
+	/// The position of the primary player at the end of the session, if the
+	/// game has one
+	pub position: Option<Vec2>,
+}
+
+/// The result of a finished replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayResult {
+	/// The outcome of the replay
+	pub outcome: Outcome,
+	/// The outcome of the recorded session
+	pub expected: Outcome,
+}
+
+impl ReplayResult {
+	/// Whether the replay had the same outcome as the recorded session
+	#[must_use]
+	pub fn matches(&self) -> bool {
+		self.outcome == self.expected
+	}
+}
+
+impl Recording {
+	/// Serialize this recording
+	///
+	/// The format is little-endian, starting with the magic bytes `WBGR`, the
+	/// format version (`u8`), the seed (`u64`), the game's name (`u8` length
+	/// and UTF-8 bytes), the outcome (`u8` flags, then a `u32` score if bit 0
+	/// is set, and two `f32` coordinates if bit 1 is set), and the number of
+	/// frames (`u32`). Each frame is its delta time in nanoseconds (`u32`) and
+	/// the number of inputs (`u8`, or `0xff` if the inputs are the same as in
+	/// the previous frame), followed by the inputs, each being the player
+	/// number (`u8`), `up` and `right` (`f32`), and flags (`u8`, bit 0 is
+	/// `is_synthetic` and bit 1 is `is_sprinting`), and then the number of
+	/// actions (`u8`, or `0xff` if they are the same as in the previous
+	/// frame), followed by the actions, each being the action's index in
+	/// [`Action::ALL`] (`u8`) and its value (`f32`).
+	///
+	/// # Panics
+	/// This function panics if the game's name is longer than 255 bytes, there
+	/// are more than `u32::MAX` frames, or a frame has 255 or more inputs or
+	/// actions
+	#[must_use]
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::new();
+
+		bytes.extend_from_slice(MAGIC);
+		bytes.push(VERSION);
+		bytes.extend_from_slice(&self.seed.to_le_bytes());
+		bytes.push(u8::try_from(self.game.len()).expect("game name too long"));
+		bytes.extend_from_slice(self.game.as_bytes());
+
+		let Outcome { score, position } = self.outcome;
+		bytes.push(u8::from(score.is_some()) | (u8::from(position.is_some()) << 1));
+
+		if let Some(score) = score {
+			bytes.extend_from_slice(&score.to_le_bytes());
+		}
+
+		if let Some(position) = position {
+			bytes.extend_from_slice(&position.x.to_le_bytes());
+			bytes.extend_from_slice(&position.y.to_le_bytes());
+		}
+
+		let frame_count = u32::try_from(self.frames.len()).expect("too many frames");
+		bytes.extend_from_slice(&frame_count.to_le_bytes());
+
+		let mut previous: Option<&Frame> = None;
+
+		for frame in &self.frames {
+			// Frames are at most `Time<Virtual>`'s maximum delta (250 ms) long
+			let delta = u32::try_from(frame.delta.as_nanos()).unwrap_or(u32::MAX);
+			bytes.extend_from_slice(&delta.to_le_bytes());
+
+			if previous.is_some_and(|p| p.inputs == frame.inputs) {
+				bytes.push(SAME);
+			} else {
+				bytes.push(count(frame.inputs.len()));
+
+				for (player, input) in &frame.inputs {
+					bytes.push(*player);
+					bytes.extend_from_slice(&input.up.to_le_bytes());
+					bytes.extend_from_slice(&input.right.to_le_bytes());
+					bytes.push(u8::from(input.is_synthetic) | (u8::from(input.is_sprinting) << 1));
+				}
+			}
+
+			if previous.is_some_and(|p| p.actions == frame.actions) {
+				bytes.push(SAME);
+			} else {
+				bytes.push(count(frame.actions.len()));
+
+				for (action, value) in &frame.actions {
+					let index = Action::ALL
+						.iter()
+						.position(|a| a == action)
+						.expect("action not in `Action::ALL`");
+
+					bytes.push(count(index));
+					bytes.extend_from_slice(&value.to_le_bytes());
+				}
+			}
+
+			previous = Some(frame);
+		}
+
+		bytes
+	}
+
+	/// Deserialize a recording (see [`to_bytes`](Recording::to_bytes) for the
+	/// format)
+	///
+	/// # Errors
+	/// This function returns an error if the recording is not valid
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+		let mut reader = Reader(bytes);
+
+		if reader.take(MAGIC.len())? != MAGIC {
+			return Err("not a `web-bg` recording".to_string());
+		}
+
+		let version = reader.u8()?;
+		if version != VERSION {
+			return Err(format!("unsupported recording version {version}"));
+		}
+
+		let seed = reader.u64()?;
+		let game_len = reader.u8()?;
+		let game = String::from_utf8(reader.take(game_len.into())?.to_vec())
+			.map_err(|_| "invalid game name in recording".to_string())?;
+
+		let flags = reader.u8()?;
+		let score = (flags & 1 != 0).then(|| reader.u32()).transpose()?;
+		let position = (flags & 2 != 0)
+			.then(|| Ok::<_, String>(Vec2::new(reader.f32()?, reader.f32()?)))
+			.transpose()?;
+
+		let frame_count = reader.u32()?;
+		let mut frames = Vec::<Frame>::new();
+
+		for _ in 0..frame_count {
+			let delta = Duration::from_nanos(reader.u32()?.into());
+
+			let inputs = match reader.u8()? {
+				SAME => frames
+					.last()
+					.map(|frame| frame.inputs.clone())
+					.ok_or_else(|| "first frame has no inputs".to_string())?,
+				count => (0..count)
+					.map(|_| {
+						let player = reader.u8()?;
+						let up = reader.f32()?;
+						let right = reader.f32()?;
+						let flags = reader.u8()?;
+
+						Ok((player, PlayerInput {
+							up,
+							right,
+							is_sprinting: flags & 2 != 0,
+							is_synthetic: flags & 1 != 0,
+						}))
+					})
+					.collect::<Result<_, String>>()?,
+			};
+
+			let actions = match reader.u8()? {
+				SAME => frames
+					.last()
+					.map(|frame| frame.actions.clone())
+					.ok_or_else(|| "first frame has no actions".to_string())?,
+				count => (0..count)
+					.map(|_| {
+						let action = Action::ALL
+							.get(usize::from(reader.u8()?))
+							.copied()
+							.ok_or_else(|| "invalid action in recording".to_string())?;
+
+						Ok((action, reader.f32()?))
+					})
+					.collect::<Result<_, String>>()?,
+			};
+
+			frames.push(Frame {
+				delta,
+				inputs,
+				actions,
+			});
+		}
+
+		if !reader.0.is_empty() {
+			return Err("unexpected data at the end of the recording".to_string());
+		}
+
+		Ok(Self {
+			seed,
+			game,
+			frames,
+			outcome: Outcome { score, position },
+		})
+	}
+
+	/// Load the recording given in the `replay` parameter, if there is one
+	///
+	/// On the web, this always returns `None`, recordings can be replayed with
+	/// the [`Controller`](crate::control::Controller) instead
+	#[must_use]
+	pub fn from_params() -> Option<Self> {
+		if cfg!(target_arch = "wasm32") {
+			return None;
+		}
+
+		let path = params::get("replay")?;
+
+		let recording = std::fs::read(&path)
+			.map_err(|e| e.to_string())
+			.and_then(|bytes| Self::from_bytes(&bytes));
+
+		match recording {
+			Ok(recording) => Some(recording),
+			Err(e) => {
+				error!("can't replay recording {path}: {e}");
+				None
+			}
+		}
+	}
+}
+
+/// Convert a count (or index) to a `u8` for a serialized recording
+///
+/// # Panics
+/// This function panics if `n` is 255 or more
+fn count(n: usize) -> u8 {
+	u8::try_from(n)
+		.ok()
+		.filter(|&n| n != SAME)
+		.expect("too many inputs or actions in a frame")
+}
+
+/// A reader for serialized recordings
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+		let (taken, rest) = self
+			.0
+			.split_at_checked(n)
+			.ok_or_else(|| "unexpected end of recording".to_string())?;
+
+		self.0 = rest;
+		Ok(taken)
+	}
+
+	fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+		Ok(self
+			.take(N)?
+			.try_into()
+			.expect("taken the wrong number of bytes"))
+	}
+
+	fn u8(&mut self) -> Result<u8, String> {
+		Ok(u8::from_le_bytes(self.array()?))
+	}
+
+	fn u32(&mut self) -> Result<u32, String> {
+		Ok(u32::from_le_bytes(self.array()?))
+	}
+
+	fn u64(&mut self) -> Result<u64, String> {
+		Ok(u64::from_le_bytes(self.array()?))
+	}
+
+	fn f32(&mut self) -> Result<f32, String> {
+		Ok(f32::from_le_bytes(self.array()?))
+	}
+}
+
+/// Get the last finished recording, serialized
+///
+/// # Panics
+/// This function panics if the recording's mutex is poisoned
+#[must_use]
+pub fn last_recording() -> Option<Vec<u8>> {
+	LAST_RECORDING
+		.lock()
+		.expect("recording mutex poisoned")
+		.clone()
+}
+
+/// How far a recording or replay has gotten
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+	/// Waiting for the game to be stopped, so that it can be started again
+	Restarting,
+	/// Waiting for the game to start again
+	Starting,
+	/// The game is running
+	Running,
+}
+
+impl Phase {
+	/// Advance the phase based on the current state, returning whether the
+	/// recording or replay should stop because the game was stopped (e.g.
+	/// switched to another game)
+	fn advance(&mut self, state: GameState, game: &str) -> bool {
+		let is_running = matches!(state, GameState::Running(name) if name == game);
+
+		match self {
+			Self::Restarting if !is_running => *self = Self::Starting,
+			Self::Starting if is_running => *self = Self::Running,
+			Self::Running if !is_running => return true,
+			_ => (),
+		}
+
+		false
+	}
+}
+
+/// A recording in progress
+#[derive(Debug, Resource)]
+pub struct Recorder {
+	recording: Recording,
+	phase: Phase,
+	/// Whether the recording should be finished (at the start of the next
+	/// frame, so that it includes the last frame's outcome)
+	stopping: bool,
+	/// Where the recording should be saved when it's finished
+	#[cfg(not(target_arch = "wasm32"))]
+	path: Option<String>,
+}
+
+/// A replay in progress
+#[derive(Debug, Resource)]
+pub struct Replayer {
+	recording: Recording,
+	phase: Phase,
+	/// The index of the next frame to replay
+	next: usize,
+	/// Whether the app should exit after the replay
+	exit: bool,
+}
+
+/// Add the recording and replay systems to the app, starting `replay` if given
+///
+/// If the `record` parameter is set (natively), recording starts right away
+pub fn build(app: &mut App, replay: Option<Recording>) {
+	app.add_systems(Update, handle.after(control::handle))
+		.add_systems(
+			RunFixedMainLoop,
+			(
+				record.run_if(resource_exists::<Recorder>),
+				replay_frame.run_if(resource_exists::<Replayer>),
+			)
+				.in_set(RunFixedMainLoopSystem::BeforeFixedMainLoop),
+		)
+		.add_systems(
+			Last,
+			(
+				replay_time.run_if(resource_exists::<Replayer>),
+				finish_on_exit.run_if(resource_exists::<Recorder>),
+			),
+		);
+
+	if let Some(recording) = replay {
+		let exit = params::get("replay-exit").is_some_and(|v| v != "false" && v != "0");
+		start_replay(&mut app.world_mut().commands(), recording, exit);
+	}
+
+	#[cfg(not(target_arch = "wasm32"))]
+	if let Some(path) = params::get("record") {
+		let state = app.world().resource::<State<GameState>>().get().to_owned();
+		let seed = app.world().resource::<Rand>().seed();
+		let mut commands = app.world_mut().commands();
+
+		if let Some(recorder) = start_recording(&mut commands, state, seed) {
+			commands.insert_resource(Recorder {
+				path: Some(path),
+				..recorder
+			});
+		}
+	}
+}
+
+/// Start recording the game in `state`, by restarting it
+///
+/// This returns the [`Recorder`], which has to be inserted into the app
+fn start_recording(commands: &mut Commands, state: GameState, seed: u64) -> Option<Recorder> {
+	let GameState::Running(game) = state else {
+		warn!("can't record while no game is running");
+		return None;
+	};
+
+	commands.insert_resource(Rand::with_seed(seed));
+	commands.send_event(SwitchGame(game.to_string()));
+
+	info!("recording '{game}' with seed {seed}");
+
+	Some(Recorder {
+		recording: Recording {
+			seed,
+			game: game.to_string(),
+			..default()
+		},
+		phase: Phase::Restarting,
+		stopping: false,
+		#[cfg(not(target_arch = "wasm32"))]
+		path: None,
+	})
+}
+
+/// Start replaying `recording`, by restarting its game with its seed
+fn start_replay(commands: &mut Commands, recording: Recording, exit: bool) {
+	if games::find(&recording.game).is_none() {
+		if exit {
+			error!("can't replay unknown game '{}'", recording.game);
+			commands.send_event(AppExit::error());
+		} else {
+			warn!("can't replay unknown game '{}'", recording.game);
+		}
+
+		return;
+	}
+
+	info!(
+		"replaying '{}' with seed {} ({} frames)",
+		recording.game,
+		recording.seed,
+		recording.frames.len()
+	);
+
+	commands.insert_resource(Rand::with_seed(recording.seed));
+	commands.send_event(SwitchGame(recording.game.clone()));
+
+	// The replay provides its own input slots
+	commands.queue(|world: &mut World| {
+		let slots = world
+			.query_filtered::<Entity, With<InputSlot>>()
+			.iter(world)
+			.collect::<Vec<_>>();
+
+		for slot in slots {
+			world.despawn(slot);
+		}
+	});
+
+	commands.insert_resource(Replayer {
+		recording,
+		phase: Phase::Restarting,
+		next: 0,
+		exit,
+	});
+}
+
+/// Handle the recording and replay [`ControlCommand`]s
+pub fn handle(
+	mut commands: Commands,
+	mut control_commands: EventReader<ControlCommand>,
+	state: Res<State<GameState>>,
+	rng: Res<Rand>,
+	mut recorder: Option<ResMut<Recorder>>,
+	replayer: Option<Res<Replayer>>,
+) {
+	for command in control_commands.read() {
+		match command {
+			ControlCommand::StartRecording if recorder.is_some() || replayer.is_some() => {
+				warn!("can't start recording while recording or replaying");
+			}
+			ControlCommand::StartRecording => {
+				if let Some(new) = start_recording(&mut commands, *state.get(), rng.seed()) {
+					commands.insert_resource(new);
+				}
+			}
+			ControlCommand::StopRecording => {
+				if let Some(recorder) = recorder.as_deref_mut() {
+					recorder.stopping = true;
+				} else {
+					warn!("can't stop recording, nothing is being recorded");
+				}
+			}
+			ControlCommand::Replay(_) if recorder.is_some() || replayer.is_some() => {
+				warn!("can't start replaying while recording or replaying");
+			}
+			ControlCommand::Replay(recording) => {
+				start_replay(&mut commands, recording.clone(), false);
+			}
+			_ => (),
+		}
+	}
+}
+
+/// Record the current frame
+#[allow(clippy::too_many_arguments)]
+pub fn record(
+	mut commands: Commands,
+	mut recorder: ResMut<Recorder>,
+	state: Res<State<GameState>>,
+	pause: Res<Pause>,
+	time: Res<Time<Virtual>>,
+	actions: Res<ActionState>,
+	slots: Query<(&InputSlot, &PlayerInput)>,
+	score: Option<Res<Score>>,
+	position: Option<Res<PlayerPosition>>,
+) {
+	let game = recorder.recording.game.clone();
+	let stopped = recorder.phase.advance(*state.get(), &game);
+
+	if recorder.stopping || stopped {
+		recorder.recording.outcome = Outcome {
+			score: score.map(|s| s.0),
+			position: position.map(|p| p.0),
+		};
+
+		commands.remove_resource::<Recorder>();
+		finish_recording(&recorder);
+		return;
+	}
+
+	if recorder.phase != Phase::Running || pause.is_paused() {
+		return;
+	}
+
+	let mut inputs = slots
+		.iter()
+		.filter_map(|(slot, input)| Some((u8::try_from(slot.player).ok()?, *input)))
+		.collect::<Vec<_>>();
+	inputs.sort_unstable_by_key(|(player, _)| *player);
+
+	let actions = Action::ALL
+		.into_iter()
+		.filter(|&action| action != Action::Pause)
+		.map(|action| (action, actions.value(action)))
+		.filter(|&(_, value)| value > 0.0)
+		.collect();
+
+	recorder.recording.frames.push(Frame {
+		delta: time.delta(),
+		inputs,
+		actions,
+	});
+}
+
+/// Finish the recording when the app exits
+pub fn finish_on_exit(
+	mut exits: EventReader<AppExit>,
+	mut recorder: ResMut<Recorder>,
+	score: Option<Res<Score>>,
+	position: Option<Res<PlayerPosition>>,
+) {
+	if exits.read().next().is_none() {
+		return;
+	}
+
+	recorder.recording.outcome = Outcome {
+		score: score.map(|s| s.0),
+		position: position.map(|p| p.0),
+	};
+
+	finish_recording(&recorder);
+}
+
+/// Save and publish a finished recording
+///
+/// # Panics
+/// This function panics if the recording's mutex is poisoned
+fn finish_recording(recorder: &Recorder) {
+	let bytes = recorder.recording.to_bytes();
+
+	info!(
+		"finished recording ({} frames, {} bytes)",
+		recorder.recording.frames.len(),
+		bytes.len()
+	);
+
+	#[cfg(not(target_arch = "wasm32"))]
+	if let Some(path) = &recorder.path
+		&& let Err(e) = std::fs::write(path, &bytes)
+	{
+		error!("can't save recording to {path}: {e}");
+	}
+
+	*LAST_RECORDING.lock().expect("recording mutex poisoned") = Some(bytes.clone());
+	events::recorded(bytes);
+}
+
+/// Replay the next recorded frame's inputs
+#[allow(clippy::too_many_arguments)]
+pub fn replay_frame(
+	mut commands: Commands,
+	mut replayer: ResMut<Replayer>,
+	state: Res<State<GameState>>,
+	pause: Res<Pause>,
+	mut actions: ResMut<ActionState>,
+	mut slots: Query<(Entity, &InputSlot, &mut PlayerInput)>,
+	score: Option<Res<Score>>,
+	position: Option<Res<PlayerPosition>>,
+	mut exits: EventWriter<AppExit>,
+) {
+	let game = replayer.recording.game.clone();
+	let stopped = replayer.phase.advance(*state.get(), &game);
+
+	let finished = replayer.phase == Phase::Running
+		&& !pause.is_paused()
+		&& replayer.next >= replayer.recording.frames.len();
+
+	if stopped || finished {
+		let result = ReplayResult {
+			outcome: Outcome {
+				score: score.map(|s| s.0),
+				position: position.map(|p| p.0),
+			},
+			expected: replayer.recording.outcome,
+		};
+
+		if stopped {
+			warn!("replay stopped early, because the game was stopped");
+		}
+
+		commands.remove_resource::<Replayer>();
+		commands.insert_resource(TimeUpdateStrategy::Automatic);
+
+		// Let the real input slots take over again
+		for (slot, ..) in &slots {
+			commands.entity(slot).despawn();
+		}
+
+		events::replayed(result);
+
+		if replayer.exit {
+			exits.write(if result.matches() {
+				AppExit::Success
+			} else {
+				AppExit::error()
+			});
+		}
+
+		return;
+	}
+
+	if replayer.phase != Phase::Running || pause.is_paused() {
+		return;
+	}
+
+	let frame = &replayer.recording.frames[replayer.next];
+
+	for (entity, slot, mut input) in &mut slots {
+		let recorded = frame
+			.inputs
+			.iter()
+			.find(|(player, _)| usize::from(*player) == slot.player);
+
+		if let Some((_, recorded)) = recorded {
+			*input = *recorded;
+		} else {
+			commands.entity(entity).despawn();
+		}
+	}
+
+	for (player, input) in &frame.inputs {
+		let player = usize::from(*player);
+
+		if !slots.iter().any(|(_, slot, _)| slot.player == player) {
+			commands.spawn((
+				InputSlot {
+					player,
+					source: InputSource::Replay,
+				},
+				*input,
+			));
+		}
+	}
+
+	// Pausing is still up to the user
+	for action in Action::ALL {
+		if action != Action::Pause {
+			let recorded = frame.actions.iter().find(|(a, _)| *a == action);
+			actions.set(action, recorded.map_or(0.0, |(_, value)| *value));
+		}
+	}
+
+	replayer.next += 1;
+}
+
+/// Make the next frame's time advance by its recorded delta time
+pub fn replay_time(replayer: Res<Replayer>, mut strategy: ResMut<TimeUpdateStrategy>) {
+	let frames = &replayer.recording.frames;
+	let next = frames.get(replayer.next).or_else(|| frames.last());
+
+	*strategy = TimeUpdateStrategy::ManualDuration(
+		next.map_or_else(|| Duration::from_secs_f64(1.0 / 60.0), |frame| frame.delta),
+	);
+}
+
+/// Whether a replay is in progress
+#[must_use]
+pub const fn replaying(replayer: Option<Res<Replayer>>) -> bool {
+	replayer.is_some()
+}
+
+#[cfg(test)]
+pub mod tests {
+	use bevy::{
+		input::InputPlugin,
+		state::app::StatesPlugin,
+		window::{PrimaryWindow, WindowOccluded},
+		winit::WinitSettings,
+	};
+
+	use super::*;
+	use crate::{
+		games::{GAMES, Minigame},
+		simulation,
+	};
+
+	/// The delta time of every frame in recordings made by tests
+	const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 30);
+
+	/// Create a headless app (without rendering, audio, or a real window) with
+	/// all games, starting with `game`
+	///
+	/// The app has a primary window entity of the default size, so that games
+	/// show the same part of the world every time.
+	#[must_use]
+	pub fn headless_app(game: &dyn Minigame, seed: u64) -> App {
+		let mut app = App::new();
+
+		app.add_plugins((
+			MinimalPlugins,
+			StatesPlugin,
+			InputPlugin,
+			AssetPlugin::default(),
+		))
+		.init_asset::<Image>()
+		.init_asset::<Mesh>()
+		.init_asset::<ColorMaterial>()
+		.init_asset::<TextureAtlasLayout>()
+		.init_asset::<Font>()
+		.add_event::<WindowOccluded>()
+		.insert_resource(WinitSettings::default())
+		.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME))
+		.insert_resource(Rand::with_seed(seed));
+
+		app.world_mut().spawn((Window::default(), PrimaryWindow));
+
+		crate::add_lifecycle_systems(&mut app, game);
+		build(&mut app, None);
+		simulation::build(&mut app);
+
+		for game in GAMES {
+			game.build(&mut app);
+		}
+
+		app
+	}
+
+	/// Create a [`headless_app`] which replays `recording`, then exits with
+	/// an error if the outcome doesn't match
+	///
+	/// # Panics
+	/// This function panics if the recording's game doesn't exist
+	#[must_use]
+	pub fn replay_app(recording: Recording) -> App {
+		let game = games::find(&recording.game).expect("unknown game");
+		let mut app = headless_app(game, recording.seed);
+
+		start_replay(&mut app.world_mut().commands(), recording, true);
+
+		app
+	}
+
+	/// Update `app` until it exits, returning how it exited
+	///
+	/// # Panics
+	/// This function panics if the app doesn't exit after `max_frames`
+	pub fn run_until_exit(app: &mut App, max_frames: usize) -> AppExit {
+		for _ in 0..max_frames {
+			app.update();
+
+			if let Some(exit) = app.should_exit() {
+				return exit;
+			}
+		}
+
+		panic!("the app didn't exit after {max_frames} frames");
+	}
+
+	/// A recording with every kind of input and action
+	fn recording() -> Recording {
+		let input = |up, right, is_sprinting, is_synthetic| PlayerInput {
+			up,
+			right,
+			is_sprinting,
+			is_synthetic,
+		};
+
+		let frames = vec![
+			Frame {
+				delta: Duration::from_millis(16),
+				inputs: vec![(0, input(0.0, 0.0, false, false))],
+				actions: vec![],
+			},
+			Frame {
+				delta: Duration::from_millis(17),
+				inputs: vec![
+					(0, input(1.0, -0.5, true, false)),
+					(2, input(0.0, 1.0, false, true)),
+				],
+				actions: vec![(Action::Sprint, 1.0), (Action::ZoomIn, 0.25)],
+			},
+			// The same inputs and actions as the previous frame
+			Frame {
+				delta: Duration::from_millis(16),
+				inputs: vec![
+					(0, input(1.0, -0.5, true, false)),
+					(2, input(0.0, 1.0, false, true)),
+				],
+				actions: vec![(Action::Sprint, 1.0), (Action::ZoomIn, 0.25)],
+			},
+			Frame {
+				delta: Duration::ZERO,
+				inputs: vec![],
+				actions: vec![(Action::ZoomOut, 0.5)],
+			},
+		];
+
+		Recording {
+			seed: 0x0123_4567_89ab_cdef,
+			game: "maze".to_string(),
+			frames,
+			outcome: Outcome {
+				score: Some(42),
+				position: Some(Vec2::new(-12.5, 1024.0)),
+			},
+		}
+	}
+
+	#[test]
+	fn round_trip() {
+		let recording = recording();
+		let bytes = recording.to_bytes();
+
+		assert_eq!(Recording::from_bytes(&bytes), Ok(recording));
+	}
+
+	#[test]
+	fn round_trip_without_outcome() {
+		let recording = Recording {
+			outcome: Outcome::default(),
+			..recording()
+		};
+
+		assert_eq!(Recording::from_bytes(&recording.to_bytes()), Ok(recording));
+	}
+
+	#[test]
+	fn repeated_frames_are_short() {
+		let mut recording = recording();
+		let len = recording.to_bytes().len();
+
+		let last = recording.frames[2].clone();
+		recording.frames.insert(3, last);
+
+		// The delta time and two counts
+		assert_eq!(recording.to_bytes().len(), len + 6);
+	}
+
+	#[test]
+	fn rejects_bad_magic() {
+		let mut bytes = recording().to_bytes();
+		bytes[..4].copy_from_slice(b"WBGX");
+
+		assert_eq!(
+			Recording::from_bytes(&bytes),
+			Err("not a `web-bg` recording".to_string())
+		);
+		assert_eq!(
+			Recording::from_bytes(b"WBG"),
+			Err("unexpected end of recording".to_string())
+		);
+	}
+
+	#[test]
+	fn rejects_unsupported_version() {
+		let mut bytes = recording().to_bytes();
+		bytes[4] = VERSION + 1;
+
+		assert_eq!(
+			Recording::from_bytes(&bytes),
+			Err(format!("unsupported recording version {}", VERSION + 1))
+		);
+	}
+
+	#[test]
+	fn rejects_truncated() {
+		let bytes = recording().to_bytes();
+
+		for len in 0..bytes.len() {
+			assert!(
+				Recording::from_bytes(&bytes[..len]).is_err(),
+				"a recording truncated to {len} bytes was accepted"
+			);
+		}
+	}
+
+	#[test]
+	fn rejects_trailing_data() {
+		let mut bytes = recording().to_bytes();
+		bytes.push(0);
+
+		assert_eq!(
+			Recording::from_bytes(&bytes),
+			Err("unexpected data at the end of the recording".to_string())
+		);
+	}
+
+	#[test]
+	fn unknown_game_exits_with_error() {
+		let _lock = control::tests::lock();
+		let mut app = headless_app(GAMES[0], 0);
+		let recording = Recording {
+			game: "unknown".to_string(),
+			..recording()
+		};
+
+		start_replay(&mut app.world_mut().commands(), recording, true);
+
+		assert_eq!(run_until_exit(&mut app, 10), AppExit::error());
+	}
+
+	/// Record the maze's autopilot walking around after nobody played for a
+	/// while, and save it as `tests/data/maze-autopilot.wbgr`, which is used
+	/// as a regression test for the maze
+	#[test]
+	#[ignore = "only for updating the checked-in recording"]
+	#[cfg(feature = "maze")]
+	fn record_maze_autopilot() {
+		/// How long to record for (the autopilot takes over after 15 seconds)
+		const DURATION: Duration = Duration::from_secs(40);
+
+		let _lock = control::tests::lock();
+		let game = games::find("maze").expect("maze not found");
+		let mut app = headless_app(game, 0x5eed);
+
+		let recorder = start_recording(&mut app.world_mut().commands(), game.state(), 0x5eed)
+			.expect("the maze isn't running");
+
+		app.insert_resource(Recorder {
+			path: Some(
+				concat!(
+					env!("CARGO_MANIFEST_DIR"),
+					"/tests/data/maze-autopilot.wbgr"
+				)
+				.to_string(),
+			),
+			..recorder
+		});
+
+		for _ in 0..DURATION.as_nanos() / FRAME_TIME.as_nanos() {
+			app.update();
+		}
+
+		app.world_mut().resource_mut::<Recorder>().stopping = true;
+		app.update();
+
+		assert!(!app.world().contains_resource::<Recorder>());
+	}
+}
@@ -0,0 +1,130 @@
+//! Fixed-timestep gameplay simulation
+//!
+//! Gameplay (e.g. movement, collision, and eating in the maze) runs in the
+//! `FixedUpdate` schedule, so that it behaves the same way at any frame rate,
+//! and plays out in exactly the same way in [replays](crate::recording). How
+//! many ticks there are per second is configured per game with a
+//! [`TickRate`].
+//!
+//! Entities which move in the simulation have a [`Position`], which the
+//! simulation updates instead of their `Transform`. Their `Transform` is then
+//! interpolated between their previous and current position every frame, so
+//! that they move smoothly even when there are more frames than ticks.
+
+use std::time::Duration;
+
+use bevy::{app::RunFixedMainLoopSystem, prelude::*};
+
+/// The tick rate used when the running game doesn't have a [`TickRate`]
+const DEFAULT_TICK_RATE: f64 = 64.0;
+
+/// A game's simulation tick rate, in ticks per second
+///
+/// Games should insert this resource when they start and remove it when they
+/// stop. Without it (or if it's not a positive, finite number), the
+/// simulation runs at 64 ticks per second.
+#[derive(Debug, Clone, Copy, PartialEq, Resource)]
+pub struct TickRate(pub f64);
+
+/// The position of an entity in the simulation
+///
+/// The entity's `Transform` (only its `x` and `y` translation) is interpolated
+/// between its position in the previous and the current tick for rendering
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+#[require(Transform)]
+pub struct Position {
+	/// The position in the current tick
+	pub current: Vec2,
+	/// The position in the previous tick
+	previous: Vec2,
+}
+
+impl Position {
+	/// Create a position, starting at `position`
+	#[must_use]
+	pub const fn new(position: Vec2) -> Self {
+		Self {
+			current: position,
+			previous: position,
+		}
+	}
+
+	/// Get the position in the previous tick
+	#[must_use]
+	pub const fn previous(&self) -> Vec2 {
+		self.previous
+	}
+
+	/// Move the position in both the current and the previous tick by
+	/// `offset`, so that the movement isn't interpolated
+	pub fn shift(&mut self, offset: Vec2) {
+		self.current += offset;
+		self.previous += offset;
+	}
+}
+
+/// Add the simulation's systems to the app
+pub fn build(app: &mut App) {
+	app.add_systems(First, apply_tick_rate)
+		.add_systems(FixedFirst, save_previous)
+		.add_systems(
+			RunFixedMainLoop,
+			interpolate.in_set(RunFixedMainLoopSystem::AfterFixedMainLoop),
+		);
+}
+
+/// Apply the running game's [`TickRate`] to the fixed timestep
+///
+/// Tick rates which are not positive, finite numbers (or which are so high
+/// that ticks would take no time at all) are rejected, and the default is used
+/// instead
+pub fn apply_tick_rate(rate: Option<Res<TickRate>>, mut time: ResMut<Time<Fixed>>) {
+	let requested = rate.as_ref().map_or(DEFAULT_TICK_RATE, |rate| rate.0);
+
+	let (rate, timestep) = timestep(requested).map_or_else(
+		|| {
+			if rate.is_some_and(|rate| rate.is_changed()) {
+				warn!("invalid tick rate {requested}, using the default of {DEFAULT_TICK_RATE}");
+			}
+
+			(
+				DEFAULT_TICK_RATE,
+				Time::<Fixed>::from_hz(DEFAULT_TICK_RATE).timestep(),
+			)
+		},
+		|timestep| (requested, timestep),
+	);
+
+	if time.timestep() != timestep {
+		debug!("simulating {rate} ticks per second");
+		time.set_timestep(timestep);
+	}
+}
+
+/// Get the duration of a tick at the given tick rate, if it's valid
+fn timestep(rate: f64) -> Option<Duration> {
+	(rate.is_finite() && rate > 0.0)
+		.then(|| Duration::try_from_secs_f64(rate.recip()).ok())
+		.flatten()
+		.filter(|timestep| !timestep.is_zero())
+}
+
+/// Remember every [`Position`] before it's updated in this tick
+pub fn save_previous(mut positions: Query<&mut Position>) {
+	for mut position in &mut positions {
+		position.previous = position.current;
+	}
+}
+
+/// Interpolate the `Transform` of every entity with a [`Position`] between its
+/// previous and current position, based on how far into the next tick this
+/// frame is
+pub fn interpolate(time: Res<Time<Fixed>>, mut positions: Query<(&Position, &mut Transform)>) {
+	let t = time.overstep_fraction();
+
+	for (position, mut trans) in &mut positions {
+		let interpolated = position.previous.lerp(position.current, t);
+		trans.translation.x = interpolated.x;
+		trans.translation.y = interpolated.y;
+	}
+}
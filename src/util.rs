@@ -1,5 +1,6 @@
 //! `web-bg` utilities and other miscellaneous things.
 
+use std::collections::HashSet;
 #[cfg(all(feature = "console_log", target_arch = "wasm32"))]
 use std::fmt::{Error as FmtError, Result as FmtResult};
 
@@ -13,37 +14,12 @@ use tracing_subscriber::{
 	layer::{Context, Filter},
 };
 pub use turborand::TurboRand;
-use turborand::rng::AtomicRng;
+use turborand::{SeededCore, rng::AtomicRng};
 
-/// Quickly declare minigames
-///
-/// Only use this macro once. It creates a const called `GAMES` with a name and
-/// [`SystemsFn`]s, the first for startup systems, the second for regular
-/// systems. This macro handles cfg features and module declarations.
-#[macro_export]
-macro_rules! games {
-	{$($feat:literal => $name:ident),*$(,)?} => {
-		$(
-			#[cfg(feature = $feat)] mod $name;
-		)*
-
-		struct Game {
-			name: &'static str,
-			start: fn(&mut bevy::app::App)
-		}
-
-		const GAMES: &[Game] = &[
-			$(
-				#[cfg(feature = $feat)] Game { name: $feat, start: $name::start },
-			)*
-		];
-
-		$(
-			#[cfg(not(feature = $feat))]
-		)*
-		compile_error!("At least one minigame must be enabled");
-	}
-}
+use crate::{
+	actions::{Action, Bindings, PRESS_THRESHOLD},
+	params,
+};
 
 /// Close all windows when the escape key (<kbd>Esc</kbd>) is pressed
 pub fn close_on_esc(
@@ -59,29 +35,113 @@ pub fn close_on_esc(
 }
 
 /// Random number generator resource
+///
+/// All randomness is derived from one seed, so that the same seed always
+/// produces the same results. Rather than using this resource's random number
+/// stream directly, systems should use a [named substream](Rand::substream),
+/// so that how much randomness one system uses doesn't affect other systems.
 #[derive(Debug, Resource, Deref, DerefMut)]
-pub struct Rand(AtomicRng);
+pub struct Rand {
+	seed: u64,
+	#[deref]
+	rng: AtomicRng,
+}
 
 impl Rand {
+	/// Create a new `Rand` with a random seed
 	#[allow(clippy::new_without_default)]
 	#[must_use]
 	pub fn new() -> Self {
-		Self(AtomicRng::new())
+		Self::with_seed(AtomicRng::new().u64(..))
 	}
 
-	pub const fn from_rng(rng: AtomicRng) -> Self {
-		Self(rng)
+	/// Create a new `Rand` with the given seed
+	#[must_use]
+	pub fn with_seed(seed: u64) -> Self {
+		Self {
+			seed,
+			rng: AtomicRng::with_seed(seed),
+		}
+	}
+
+	/// Create a new `Rand` with the seed supplied via the `seed` parameter, or
+	/// a random seed if there is none
+	///
+	/// Seeds which are not integers are hashed, so any string can be used as a
+	/// seed
+	#[must_use]
+	pub fn from_params() -> Self {
+		params::get("seed").map_or_else(Self::new, |seed| {
+			Self::with_seed(seed.trim().parse().unwrap_or_else(|_| hash(&seed)))
+		})
 	}
+
+	/// Get the seed this `Rand` was created with
+	#[must_use]
+	pub const fn seed(&self) -> u64 {
+		self.seed
+	}
+
+	/// Create a new `Rand` deterministically derived from this one's seed and
+	/// the given `key`
+	///
+	/// This does not advance this `Rand`, so the derived `Rand` is the same
+	/// regardless of what this one has been used for
+	#[must_use]
+	pub fn derive(&self, key: u64) -> Self {
+		Self::with_seed(mix(self.seed ^ mix(key)))
+	}
+
+	/// Get the substream called `name` (e.g. `"maze-gen"` or `"flicker"`)
+	///
+	/// Each substream is an independently seeded `Rand` derived from this one's
+	/// seed and the substream's name, so the same name always gives the same
+	/// substream, and using one substream never affects another. Every call
+	/// returns a new `Rand` starting at the beginning of the substream, so
+	/// systems that use a substream over multiple frames should keep it around
+	/// (e.g. in a component or a `Local`).
+	#[must_use]
+	pub fn substream(&self, name: &str) -> Self {
+		self.derive(hash(name))
+	}
+}
+
+/// Mix the bits of `x` (the `SplitMix64` finalizer)
+const fn mix(mut x: u64) -> u64 {
+	x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+	x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	x ^ (x >> 31)
+}
+
+/// Hash a string (using 64-bit FNV-1a)
+fn hash(s: &str) -> u64 {
+	s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+		(hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+	})
 }
 
-/// Up/down/left/right movement input within the range from `-1.0` to `1.0`
+/// The set of systems which write [`PlayerInput`]s (e.g. [`input`], or a
+/// game's autopilot)
+///
+/// These systems run in `PreUpdate`, and don't run during replays
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub struct PlayerInputSet;
+
+/// Up/down/left/right movement input within the range from `-1.0` to `1.0`,
+/// and whether to sprint, for the player of an [`InputSlot`]
 ///
 /// If the input for either axis is within the deadzone, it is set to exactly
 /// `0.0`
-#[derive(Debug, Clone, Copy, Resource, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Component, Default)]
 pub struct PlayerInput {
 	pub up: f32,
 	pub right: f32,
+	/// Whether the [`Sprint`](Action::Sprint) action is pressed
+	pub is_sprinting: bool,
+	/// Whether this input was generated by the game itself (e.g. by an
+	/// autopilot) instead of coming from a player
+	pub is_synthetic: bool,
 }
 
 impl PlayerInput {
@@ -93,13 +153,13 @@ impl PlayerInput {
 }
 
 impl From<PlayerInput> for Vec2 {
-	fn from(PlayerInput { up, right }: PlayerInput) -> Self {
+	fn from(PlayerInput { up, right, .. }: PlayerInput) -> Self {
 		Self { x: right, y: up }
 	}
 }
 
 impl From<PlayerInput> for Vec3 {
-	fn from(PlayerInput { up, right }: PlayerInput) -> Self {
+	fn from(PlayerInput { up, right, .. }: PlayerInput) -> Self {
 		Self {
 			x: right,
 			y: up,
@@ -108,94 +168,224 @@ impl From<PlayerInput> for Vec3 {
 	}
 }
 
-/// A system for processing up/down/left/right movement input, shared across
-/// games
-///
-/// # Usage
+/// An input slot, which provides the [`PlayerInput`] for one (local) player
 ///
-/// Insert the [`PlayerInput`] resource into the app on startup (this is not
-/// done automatically), then register this system, ideally before any
-/// movement/animation processing (e.g. in the `PreUpdate`) schedule
-pub fn input(
-	mut input: ResMut<PlayerInput>,
-	key_input: Res<ButtonInput<KeyCode>>,
-	gamepads: Query<&Gamepad>,
-) {
-	const DEADZONE: f32 = 0.05;
-
-	let mut up = 0.0;
-	let mut right = 0.0;
-
-	// Keyboard WASD
-	if key_input.pressed(KeyCode::KeyW) {
-		up += 1.0;
-	}
-
-	if key_input.pressed(KeyCode::KeyS) {
-		up -= 1.0;
-	}
-
-	if key_input.pressed(KeyCode::KeyD) {
-		right += 1.0;
-	}
+/// Input slots are global (they are kept when switching games), and are
+/// managed by [`update_input_slots`]. Games should spawn a player for each
+/// input slot, or only use the primary slot's input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Component)]
+#[require(PlayerInput)]
+pub struct InputSlot {
+	/// The player's number, starting from `0` for the primary player
+	pub player: usize,
+	/// Where this slot's input comes from
+	pub source: InputSource,
+}
 
-	if key_input.pressed(KeyCode::KeyA) {
-		right -= 1.0;
+impl InputSlot {
+	/// Whether this is the primary player's input slot
+	#[must_use]
+	pub const fn is_primary(&self) -> bool {
+		self.player == 0
 	}
+}
 
-	// Keyboard arrow keys
-	if key_input.pressed(KeyCode::ArrowUp) {
-		up += 1.0;
-	}
+/// Where an [`InputSlot`]'s input comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputSource {
+	/// The first key binding of each action (WASD by default), mouse bindings,
+	/// and pointers
+	Keyboard,
+	/// The second key binding of each action (arrow keys by default)
+	KeyboardAlt,
+	/// The gamepad with this entity
+	Gamepad(Entity),
+	/// A [replay](crate::recording)
+	Replay,
+}
 
-	if key_input.pressed(KeyCode::ArrowDown) {
-		up -= 1.0;
-	}
+/// The movement actions, as `(up, down, left, right)`
+const MOVEMENT: [Action; 4] = [
+	Action::MoveUp,
+	Action::MoveDown,
+	Action::MoveLeft,
+	Action::MoveRight,
+];
 
-	if key_input.pressed(KeyCode::ArrowRight) {
-		right += 1.0;
+/// A system for adding and removing [`InputSlot`]s as players join and leave
+///
+/// There is always a slot for the keyboard (and pointers), which is the
+/// primary slot. Other players join explicitly by pressing
+/// [`Interact`](Action::Interact) with the alternative keyboard keys (`Space`
+/// by default) or on a gamepad, which adds a slot for that input source. Until
+/// then, the primary slot also gets input from that source. A gamepad's slot is
+/// removed when it disconnects. New slots get the lowest free player number.
+pub fn update_input_slots(
+	mut commands: Commands,
+	slots: Query<(Entity, &InputSlot)>,
+	gamepads: Query<(Entity, &Gamepad)>,
+	bindings: Res<Bindings>,
+	keys: Res<ButtonInput<KeyCode>>,
+) {
+	let mut players = slots
+		.iter()
+		.map(|(_, slot)| slot.player)
+		.collect::<HashSet<_>>();
+
+	let mut join = |source| {
+		// There are always fewer players than numbers
+		let player = (0..=players.len())
+			.find(|n| !players.contains(n))
+			.unwrap_or_default();
+
+		players.insert(player);
+		debug!("player {player} joined with {source:?}");
+		commands.spawn(InputSlot { player, source });
+	};
+
+	let has_source = |source| slots.iter().any(|(_, slot)| slot.source == source);
+
+	if !has_source(InputSource::Keyboard) {
+		join(InputSource::Keyboard);
 	}
 
-	if key_input.pressed(KeyCode::ArrowLeft) {
-		right -= 1.0;
+	if bindings.key_value(Action::Interact, 1, &keys) >= PRESS_THRESHOLD
+		&& !has_source(InputSource::KeyboardAlt)
+	{
+		join(InputSource::KeyboardAlt);
 	}
 
-	for gamepad in gamepads.iter() {
-		// Gamepad buttons
-		if gamepad.pressed(GamepadButton::DPadUp) {
-			up += 1.0;
+	for (entity, gamepad) in &gamepads {
+		if bindings.gamepad_value(Action::Interact, gamepad) >= PRESS_THRESHOLD
+			&& !has_source(InputSource::Gamepad(entity))
+		{
+			join(InputSource::Gamepad(entity));
 		}
+	}
 
-		if gamepad.pressed(GamepadButton::DPadDown) {
-			up -= 1.0;
+	for (entity, slot) in &slots {
+		if let InputSource::Gamepad(gamepad) = slot.source
+			&& !gamepads.contains(gamepad)
+		{
+			debug!("player {} left", slot.player);
+			commands.entity(entity).despawn();
 		}
+	}
+}
 
-		if gamepad.pressed(GamepadButton::DPadRight) {
-			right += 1.0;
-		}
+/// How far (in logical pixels) a pointer has to be dragged for full speed
+const POINTER_JOYSTICK_RADIUS: f32 = 64.0;
 
-		if gamepad.pressed(GamepadButton::DPadLeft) {
-			right -= 1.0;
-		}
+/// A system for processing up/down/left/right movement and sprint input,
+/// shared across games
+///
+/// Each [`InputSlot`]'s input comes from the movement and
+/// [`Sprint`](Action::Sprint) [`Action`]s, but only from that slot's
+/// [`InputSource`] (by default WASD and left shift for the keyboard, arrow keys
+/// and right shift for the alternative keyboard slot, and the D-pad, left
+/// stick, and left stick press for gamepads). The primary slot also gets input
+/// from every source which doesn't have its own slot (so that e.g. the arrow
+/// keys and a lone gamepad work before anyone else joins), and from pointers,
+/// which work like a virtual joystick: pressing anywhere (with a finger or the
+/// left mouse button) and dragging moves in the direction of the drag, with
+/// full speed after [`POINTER_JOYSTICK_RADIUS`] pixels.
+///
+/// # Usage
+///
+/// Register this system in the `PreUpdate` schedule, in the
+/// [`PlayerInputSet`]
+#[allow(clippy::too_many_arguments)]
+pub fn input(
+	mut slots: Query<(&InputSlot, &mut PlayerInput)>,
+	bindings: Res<Bindings>,
+	keys: Res<ButtonInput<KeyCode>>,
+	gamepads: Query<(Entity, &Gamepad)>,
+	mouse_input: Res<ButtonInput<MouseButton>>,
+	touches: Res<Touches>,
+	windows: Query<&Window>,
+	mut drag_start: Local<Option<Vec2>>,
+) {
+	const DEADZONE: f32 = 0.05;
 
-		// Gamepad stick
-		let Vec2 { x, y } = gamepad.left_stick();
+	// Pointer (touch or mouse) dragging
+	let cursor = windows.iter().find_map(Window::cursor_position);
 
-		if y.abs() > DEADZONE {
-			up += y;
-		}
+	if mouse_input.just_pressed(MouseButton::Left) {
+		*drag_start = cursor;
+	} else if !mouse_input.pressed(MouseButton::Left) {
+		*drag_start = None;
+	}
 
-		if x.abs() > DEADZONE {
-			right += x;
+	let drag = touches
+		.iter()
+		.next()
+		.map(|touch| touch.position() - touch.start_position())
+		.or_else(|| Some(cursor? - (*drag_start)?));
+
+	let sources = slots
+		.iter()
+		.map(|(slot, _)| slot.source)
+		.collect::<HashSet<_>>();
+
+	for (slot, mut input) in &mut slots {
+		let value = |action| {
+			let value = match slot.source {
+				InputSource::Keyboard => bindings
+					.key_value(action, 0, &keys)
+					.max(bindings.mouse_value(action, &mouse_input)),
+				InputSource::KeyboardAlt => bindings.key_value(action, 1, &keys),
+				InputSource::Gamepad(gamepad) => gamepads
+					.get(gamepad)
+					.map_or(0.0, |(_, gamepad)| bindings.gamepad_value(action, gamepad)),
+				InputSource::Replay => 0.0,
+			};
+
+			if !slot.is_primary() {
+				return value;
+			}
+
+			// Sources without their own slot control the primary player
+			let alt = if sources.contains(&InputSource::KeyboardAlt) {
+				0.0
+			} else {
+				bindings.key_value(action, 1, &keys)
+			};
+
+			gamepads
+				.iter()
+				.filter(|(entity, _)| !sources.contains(&InputSource::Gamepad(*entity)))
+				.map(|(_, gamepad)| bindings.gamepad_value(action, gamepad))
+				.fold(value.max(alt), f32::max)
+		};
+
+		let [up, down, left, right] = MOVEMENT.map(value);
+		let is_sprinting = value(Action::Sprint) >= PRESS_THRESHOLD;
+
+		let mut up = up - down;
+		let mut right = right - left;
+
+		if let Some(drag) = drag.filter(|_| slot.is_primary()) {
+			// Screen coordinates point down, but `up` points up
+			let Vec2 { x, y } = (drag / POINTER_JOYSTICK_RADIUS).clamp_length_max(1.0);
+
+			if y.abs() > DEADZONE {
+				up -= y;
+			}
+
+			if x.abs() > DEADZONE {
+				right += x;
+			}
 		}
-	}
 
-	let up = if up.abs() > DEADZONE { up } else { 0.0 };
-	let right = if right.abs() > DEADZONE { right } else { 0.0 };
+		let up = if up.abs() > DEADZONE { up } else { 0.0 };
+		let right = if right.abs() > DEADZONE { right } else { 0.0 };
 
-	*input = PlayerInput {
-		up: up.clamp(-1.0, 1.0),
-		right: right.clamp(-1.0, 1.0),
+		*input = PlayerInput {
+			up: up.clamp(-1.0, 1.0),
+			right: right.clamp(-1.0, 1.0),
+			is_sprinting,
+			is_synthetic: false,
+		};
 	}
 }
 
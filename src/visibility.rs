@@ -0,0 +1,94 @@
+//! Pausing the game while it can't be seen
+//!
+//! On the web, the game is paused while the page is hidden (e.g. in a
+//! background tab), based on the document's `visibilitychange` event, and while
+//! the `#background` canvas is scrolled out of view, based on an
+//! `IntersectionObserver`. Natively, the game is paused while the window is
+//! occluded (e.g. minimized).
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::{prelude::*, window::WindowOccluded};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::*;
+#[cfg(target_arch = "wasm32")]
+use web_sys::{IntersectionObserver, IntersectionObserverEntry};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::control::Pause;
+use crate::control::PauseReason;
+#[cfg(target_arch = "wasm32")]
+use crate::control::{self, ControlCommand};
+
+/// Start listening for page visibility and canvas intersection changes
+///
+/// Changes are sent to the app as [`ControlCommand`]s
+///
+/// # Panics
+/// This function panics if the `window`, `document`, or `#background` canvas
+/// are not available, or if adding the listeners throws
+#[cfg(target_arch = "wasm32")]
+pub fn init() {
+	let document = web_sys::window()
+		.expect("JS `window` not available")
+		.document()
+		.expect("JS `document` not available");
+
+	let on_visibility_change = Closure::<dyn Fn()>::new({
+		let document = document.clone();
+		move || set_paused(PauseReason::Hidden, document.hidden())
+	});
+
+	document
+		.add_event_listener_with_callback(
+			"visibilitychange",
+			on_visibility_change.as_ref().unchecked_ref(),
+		)
+		.expect("JS `addEventListener` failed");
+
+	// The listener must live as long as the page
+	on_visibility_change.forget();
+
+	let on_intersection = Closure::<dyn Fn(js_sys::Array)>::new(|entries: js_sys::Array| {
+		// Entries are in chronological order, so the last one is the current state
+		if let Some(entry) = entries.iter().last() {
+			let entry = entry.unchecked_into::<IntersectionObserverEntry>();
+			set_paused(PauseReason::Offscreen, !entry.is_intersecting());
+		}
+	});
+
+	let observer = IntersectionObserver::new(on_intersection.as_ref().unchecked_ref())
+		.expect("JS `new IntersectionObserver(...)` failed");
+
+	// The observer and its callback must live as long as the page
+	on_intersection.forget();
+
+	let canvas = document
+		.get_element_by_id("background")
+		.expect("`#background` canvas not found");
+
+	observer.observe(&canvas);
+
+	set_paused(PauseReason::Hidden, document.hidden());
+}
+
+/// Pause or resume the game for the given reason
+#[cfg(target_arch = "wasm32")]
+fn set_paused(reason: PauseReason, paused: bool) {
+	control::send(if paused {
+		ControlCommand::Pause(reason)
+	} else {
+		ControlCommand::Resume(reason)
+	});
+}
+
+/// Pause the game while the window is occluded
+#[cfg(not(target_arch = "wasm32"))]
+pub fn window_occlusion(mut occlusions: EventReader<WindowOccluded>, mut pause: ResMut<Pause>) {
+	for occlusion in occlusions.read() {
+		if occlusion.occluded {
+			pause.pause(PauseReason::Hidden);
+		} else {
+			pause.resume(PauseReason::Hidden);
+		}
+	}
+}